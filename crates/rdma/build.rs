@@ -39,6 +39,13 @@ fn main() {
         link_rdma_core(lib_name, pkg_name, version, &mut include_paths);
     }
 
+    if env::var("CARGO_FEATURE_MLX5").is_ok() {
+        let lib_name = "libmlx5";
+        let pkg_name = "libibverbs-dev";
+        let version = "1.10.28";
+        link_rdma_core(lib_name, pkg_name, version, &mut include_paths);
+    }
+
     {
         include_paths.sort_unstable();
         include_paths.dedup_by(|x, first| x == first);
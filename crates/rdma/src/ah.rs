@@ -1,6 +1,6 @@
 use crate::bindings as C;
 use crate::device::Gid;
-use crate::error::create_resource;
+use crate::error::{check_drop, create_resource};
 use crate::pd::ProtectionDomain;
 
 use std::io;
@@ -16,6 +16,16 @@ impl AddressHandle {
         self.0.ffi_ptr()
     }
 
+    /// Returns the underlying `ibv_ah` pointer
+    ///
+    /// The pointer is valid as long as the address handle is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_ah {
+        self.ffi_ptr()
+    }
+
     #[inline]
     #[must_use]
     pub fn options() -> AddressHandleOptions {
@@ -38,6 +48,29 @@ impl AddressHandle {
         };
         Ok(Self(owner))
     }
+
+    /// Creates an address handle which sends to the multicast group `gid`
+    ///
+    /// The destination LID is set to the multicast LID `lid`, and
+    /// the GRH is sourced from GID index 0 with a hop limit of 64.
+    /// Returns an `InvalidInput` error if `gid` is not a multicast GID.
+    ///
+    /// See [`QueuePair::attach_mcast`](crate::qp::QueuePair::attach_mcast).
+    #[inline]
+    pub fn create_multicast(
+        pd: &ProtectionDomain,
+        gid: Gid,
+        lid: u16,
+        port_num: u8,
+    ) -> io::Result<Self> {
+        if !gid.is_multicast() {
+            let msg = "the destination gid is not a multicast gid";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let mut options = Self::options();
+        options.dest_lid(lid).port_num(port_num).roce(gid, 0);
+        Self::create(pd, options)
+    }
 }
 
 struct Owner {
@@ -63,7 +96,7 @@ impl Drop for Owner {
         unsafe {
             let ah = self.ffi_ptr();
             let ret = C::ibv_destroy_ah(ah);
-            assert_eq!(ret, 0);
+            check_drop(ret, "ibv_destroy_ah");
         }
     }
 }
@@ -88,6 +121,10 @@ impl AddressHandleOptions {
         self.attr
     }
 
+    pub(crate) fn from_ctype(attr: C::ibv_ah_attr) -> Self {
+        Self { attr }
+    }
+
     #[inline]
     pub fn dest_lid(&mut self, dest_lid: u16) -> &mut Self {
         self.attr.dlid = dest_lid;
@@ -112,6 +149,21 @@ impl AddressHandleOptions {
         self.attr.grh = global_route_header.into_ctype();
         self
     }
+
+    /// Sets a global route header with the default routing parameters
+    ///
+    /// This is the common case of `RoCE`, which requires a GRH on every packet.
+    /// The hop limit is 64, the flow label and the traffic class are 0.
+    #[inline]
+    pub fn roce(&mut self, dest_gid: Gid, sgid_index: u8) -> &mut Self {
+        self.global_route_header(GlobalRoute {
+            dest_gid,
+            flow_label: 0,
+            sgid_index,
+            hop_limit: 64,
+            traffic_class: 0,
+        })
+    }
 }
 
 #[repr(C)]
@@ -120,10 +172,33 @@ pub struct GlobalRoute {
     pub flow_label: u32,
     pub sgid_index: u8,
     pub hop_limit: u8,
+    /// The traffic class byte of the GRH
+    ///
+    /// On `RoCE` v2 it is the IP traffic class: the upper 6 bits are the DSCP
+    /// and the lower 2 bits are the ECN. Use [`with_dscp`](Self::with_dscp) to set the DSCP.
     pub traffic_class: u8,
 }
 
 impl GlobalRoute {
+    /// Sets the DSCP in the upper 6 bits of the traffic class, keeping the ECN bits
+    ///
+    /// # Panics
+    /// + if `dscp` does not fit in 6 bits
+    #[inline]
+    #[must_use]
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        assert!(dscp < 64, "dscp must fit in 6 bits");
+        self.traffic_class = (dscp << 2) | (self.traffic_class & 0b11);
+        self
+    }
+
+    /// Returns the DSCP in the upper 6 bits of the traffic class
+    #[inline]
+    #[must_use]
+    pub fn dscp(&self) -> u8 {
+        self.traffic_class >> 2
+    }
+
     fn into_ctype(self) -> C::ibv_global_route {
         // SAFETY: same repr
         unsafe { mem::transmute(self) }
@@ -136,6 +211,20 @@ mod tests {
 
     use rust_utils::offset_of;
 
+    #[test]
+    fn dscp_encoding() {
+        let grh = GlobalRoute {
+            dest_gid: Gid::from_bytes([0; 16]),
+            flow_label: 0,
+            sgid_index: 0,
+            hop_limit: 64,
+            traffic_class: 0b01,
+        };
+        let grh = grh.with_dscp(46);
+        assert_eq!(grh.traffic_class, 0xb9);
+        assert_eq!(grh.dscp(), 46);
+    }
+
     #[test]
     fn global_route_layout() {
         assert_eq!(
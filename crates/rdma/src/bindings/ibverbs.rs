@@ -190,6 +190,66 @@ pub unsafe fn ibv_poll_cq(cq: *mut ibv_cq, num_entries: c_int, wc: *mut ibv_wc)
     (op)(cq, num_entries, wc)
 }
 
+#[inline]
+pub unsafe fn ibv_start_poll(cq: *mut ibv_cq_ex, attr: *mut ibv_poll_cq_attr) -> c_int {
+    let op = (*cq).start_poll.unwrap_unchecked();
+    (op)(cq, attr)
+}
+
+#[inline]
+pub unsafe fn ibv_next_poll(cq: *mut ibv_cq_ex) -> c_int {
+    let op = (*cq).next_poll.unwrap_unchecked();
+    (op)(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_end_poll(cq: *mut ibv_cq_ex) {
+    let op = (*cq).end_poll.unwrap_unchecked();
+    (op)(cq);
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_opcode(cq: *mut ibv_cq_ex) -> ibv_wc_opcode {
+    let op = (*cq).read_opcode.unwrap_unchecked();
+    (op)(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_vendor_err(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_vendor_err.unwrap_unchecked();
+    (op)(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_byte_len(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_byte_len.unwrap_unchecked();
+    (op)(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_imm_data(cq: *mut ibv_cq_ex) -> __be32 {
+    let op = (*cq).read_imm_data.unwrap_unchecked();
+    (op)(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_qp_num(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_qp_num.unwrap_unchecked();
+    (op)(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_completion_ts(cq: *mut ibv_cq_ex) -> u64 {
+    let op = (*cq).read_completion_ts.unwrap_unchecked();
+    (op)(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_wc_flags(cq: *mut ibv_cq_ex) -> c_uint {
+    let op = (*cq).read_wc_flags.unwrap_unchecked();
+    (op)(cq)
+}
+
 #[inline]
 pub unsafe fn ibv_alloc_mw(pd: *mut ibv_pd, mw_type: ibv_mw_type) -> *mut ibv_mw {
     let ctx: *mut ibv_context = (*pd).context;
@@ -258,6 +318,118 @@ pub unsafe fn ibv_free_dm(dm: *mut ibv_dm) -> c_int {
     (op)(dm)
 }
 
+#[inline]
+pub unsafe fn ibv_memcpy_to_dm(
+    dm: *mut ibv_dm,
+    dm_offset: u64,
+    host_addr: *const c_void,
+    length: usize,
+) -> c_int {
+    let op = (*dm).memcpy_to_dm.unwrap_unchecked();
+    (op)(dm, dm_offset, host_addr, length)
+}
+
+#[inline]
+pub unsafe fn ibv_memcpy_from_dm(
+    host_addr: *mut c_void,
+    dm: *mut ibv_dm,
+    dm_offset: u64,
+    length: usize,
+) -> c_int {
+    let op = (*dm).memcpy_from_dm.unwrap_unchecked();
+    (op)(host_addr, dm, dm_offset, length)
+}
+
+#[inline]
+pub unsafe fn ibv_reg_dm_mr(
+    pd: *mut ibv_pd,
+    dm: *mut ibv_dm,
+    dm_offset: u64,
+    length: usize,
+    access: c_uint,
+) -> *mut ibv_mr {
+    let vctx: *mut verbs_context = verbs_get_ctx_op!((*pd).context, reg_dm_mr);
+
+    if vctx.is_null() {
+        set_errno(EOPNOTSUPP);
+        return ptr::null_mut();
+    }
+
+    let op = (*vctx).reg_dm_mr.unwrap_unchecked();
+    (op)(pd, dm, dm_offset, length, access)
+}
+
+#[inline]
+pub unsafe fn ibv_query_rt_values_ex(
+    context: *mut ibv_context,
+    values: *mut ibv_values_ex,
+) -> c_int {
+    let vctx: *mut verbs_context = verbs_get_ctx_op!(context, query_rt_values);
+
+    if vctx.is_null() {
+        return EOPNOTSUPP;
+    }
+
+    let op = (*vctx).query_rt_values.unwrap_unchecked();
+    (op)(context, values)
+}
+
+#[inline]
+pub unsafe fn ibv_modify_cq(cq: *mut ibv_cq, attr: *mut ibv_modify_cq_attr) -> c_int {
+    let vctx: *mut verbs_context = verbs_get_ctx_op!((*cq).context, modify_cq);
+
+    if vctx.is_null() {
+        return EOPNOTSUPP;
+    }
+
+    let op = (*vctx).modify_cq.unwrap_unchecked();
+    (op)(cq, attr)
+}
+
+#[inline]
+pub unsafe fn ibv_alloc_td(
+    context: *mut ibv_context,
+    init_attr: *mut ibv_td_init_attr,
+) -> *mut ibv_td {
+    let vctx: *mut verbs_context = verbs_get_ctx_op!(context, alloc_td);
+
+    if vctx.is_null() {
+        set_errno(EOPNOTSUPP);
+        return ptr::null_mut();
+    }
+
+    let op = (*vctx).alloc_td.unwrap_unchecked();
+    (op)(context, init_attr)
+}
+
+#[inline]
+pub unsafe fn ibv_dealloc_td(td: *mut ibv_td) -> c_int {
+    let vctx: *mut verbs_context = verbs_get_ctx_op!((*td).context, dealloc_td);
+
+    if vctx.is_null() {
+        return EOPNOTSUPP;
+    }
+
+    let op = (*vctx).dealloc_td.unwrap_unchecked();
+    (op)(td)
+}
+
+#[inline]
+pub unsafe fn ibv_alloc_parent_domain(
+    context: *mut ibv_context,
+    attr: *mut ibv_parent_domain_init_attr,
+) -> *mut ibv_pd {
+    let vctx: *mut verbs_context = verbs_get_ctx_op!(context, alloc_parent_domain);
+
+    if vctx.is_null() {
+        set_errno(EOPNOTSUPP);
+        return ptr::null_mut();
+    }
+
+    let op = (*vctx).alloc_parent_domain.unwrap_unchecked();
+    (op)(context, attr)
+}
+
 #[inline]
 pub unsafe fn ibv_post_send(
     qp: *mut ibv_qp,
@@ -280,6 +452,17 @@ pub unsafe fn ibv_post_recv(
     (op)(qp, wr, bad_wr)
 }
 
+#[inline]
+pub unsafe fn ibv_post_srq_recv(
+    srq: *mut ibv_srq,
+    recv_wr: *mut ibv_recv_wr,
+    bad_recv_wr: *mut *mut ibv_recv_wr,
+) -> c_int {
+    let ctx: *mut ibv_context = (*srq).context;
+    let op = (*ctx).ops.post_srq_recv.unwrap_unchecked();
+    (op)(srq, recv_wr, bad_recv_wr)
+}
+
 #[inline]
 pub unsafe fn ibv_reg_mr(
     pd: *mut ibv_pd,
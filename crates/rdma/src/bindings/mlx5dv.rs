@@ -0,0 +1,95 @@
+//! libmlx5 direct verbs (`infiniband/mlx5dv.h`)
+//!
+//! Only the parts which are required by the DC transport are declared.
+#![allow(
+    non_camel_case_types,
+    clippy::missing_safety_doc,
+    clippy::inline_always,
+    clippy::undocumented_unsafe_blocks
+)]
+
+use super::*;
+
+use std::mem;
+
+pub const MLX5DV_QP_INIT_ATTR_MASK_QP_CREATE_FLAGS: u64 = 1 << 0;
+pub const MLX5DV_QP_INIT_ATTR_MASK_DC: u64 = 1 << 1;
+pub const MLX5DV_QP_INIT_ATTR_MASK_SEND_OPS_FLAGS: u64 = 1 << 2;
+
+pub type mlx5dv_dc_type = c_uint;
+pub const MLX5DV_DCTYPE_DCT: mlx5dv_dc_type = 1;
+pub const MLX5DV_DCTYPE_DCI: mlx5dv_dc_type = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct mlx5dv_dci_streams {
+    pub log_num_concurent: u8,
+    pub log_num_errored: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union mlx5dv_dc_init_attr_union {
+    pub dct_access_key: u64,
+    pub dci_streams: mlx5dv_dci_streams,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct mlx5dv_dc_init_attr {
+    pub dc_type: mlx5dv_dc_type,
+    pub u: mlx5dv_dc_init_attr_union,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct mlx5dv_qp_init_attr {
+    pub comp_mask: u64,
+    pub create_flags: u32,
+    pub dc_init_attr: mlx5dv_dc_init_attr,
+    pub send_ops_flags: u64,
+}
+
+/// The leading fields of `struct mlx5dv_qp_ex`
+///
+/// The struct is allocated by the driver and only accessed through pointers.
+#[repr(C)]
+pub struct mlx5dv_qp_ex {
+    pub comp_mask: u64,
+    pub wr_set_dc_addr: Option<
+        unsafe extern "C" fn(
+            mqp: *mut mlx5dv_qp_ex,
+            ah: *mut ibv_ah,
+            remote_dctn: u32,
+            remote_dc_key: u64,
+        ),
+    >,
+}
+
+const _: () = {
+    assert!(mem::size_of::<mlx5dv_dc_init_attr>() == 16);
+    assert!(mem::size_of::<mlx5dv_qp_init_attr>() == 40);
+    assert!(offset_of!(mlx5dv_qp_init_attr, dc_init_attr) == 16);
+    assert!(offset_of!(mlx5dv_qp_init_attr, send_ops_flags) == 32);
+};
+
+extern "C" {
+    pub fn mlx5dv_create_qp(
+        context: *mut ibv_context,
+        qp_attr: *mut ibv_qp_init_attr_ex,
+        mlx5_qp_attr: *mut mlx5dv_qp_init_attr,
+    ) -> *mut ibv_qp;
+
+    pub fn mlx5dv_qp_ex_from_ibv_qp_ex(qp: *mut ibv_qp_ex) -> *mut mlx5dv_qp_ex;
+}
+
+#[inline(always)]
+pub unsafe fn mlx5dv_wr_set_dc_addr(
+    mqp: *mut mlx5dv_qp_ex,
+    ah: *mut ibv_ah,
+    remote_dctn: u32,
+    remote_dc_key: u64,
+) {
+    let op = (*mqp).wr_set_dc_addr.unwrap_unchecked();
+    (op)(mqp, ah, remote_dctn, remote_dc_key);
+}
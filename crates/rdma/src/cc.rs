@@ -1,17 +1,59 @@
 use crate::bindings as C;
 use crate::cq::{self, CompletionQueue};
 use crate::ctx::Context;
-use crate::error::{create_resource, custom_error};
+use crate::error::{check_drop, create_resource, custom_error, last_error};
 use crate::weakset::WeakSet;
 
-use std::os::raw::c_void;
-use std::os::unix::prelude::{AsRawFd, RawFd};
+use std::os::raw::{c_int, c_void};
+use std::os::unix::prelude::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
 use std::ptr::NonNull;
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 use std::{io, ptr};
 
+use numeric_cast::NumericCast;
 use parking_lot::Mutex;
 
+/// A completion event channel
+///
+/// The channel is a file descriptor which becomes readable when a completion event arrives.
+/// It can be integrated with any async runtime:
+///
+/// 1. call [`CompChannel::set_nonblocking`]
+/// 2. register the fd ([`AsFd`] or [`AsRawFd`]) with the reactor of the runtime
+///    (e.g. `async_io::Async`, `tokio::io::unix::AsyncFd` or `mio::unix::SourceFd`)
+/// 3. when the fd is readable, call [`CompChannel::try_wait_cq_event`] until it returns `None`
+///
+/// One channel can be shared by multiple completion queues.
+/// Each event carries the queue which fired, so a single thread can wait on all of them
+/// and demultiplex by [`CompletionQueue::user_data`]:
+///
+/// ```no_run
+/// use rdma::cc::CompChannel;
+/// use rdma::cq::CompletionQueue;
+/// use rdma::ctx::Context;
+///
+/// fn run(ctx: &Context) -> std::io::Result<()> {
+///     let cc = CompChannel::create(ctx)?;
+///     let mut cqs = Vec::new();
+///     for id in 0..4 {
+///         let mut options = CompletionQueue::options();
+///         options.cqe(64).user_data(id).channel(&cc);
+///         let cq = CompletionQueue::create(ctx, options)?;
+///         cq.req_notify_all()?;
+///         cqs.push(cq);
+///     }
+///     assert_eq!(cc.cq_count(), 4);
+///
+///     loop {
+///         let Some(cq) = cc.wait_cq_event()? else { continue };
+///         cq.ack_cq_events(1);
+///         cq.req_notify_all()?;
+///         println!("cq {} fired", cq.user_data());
+///         // poll `cq` here
+///     }
+/// }
+/// ```
 #[derive(Clone)]
 pub struct CompChannel(Arc<Owner>);
 
@@ -20,6 +62,16 @@ impl CompChannel {
         self.0.ffi_ptr()
     }
 
+    /// Returns the underlying `ibv_comp_channel` pointer
+    ///
+    /// The pointer is valid as long as the channel is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_comp_channel {
+        self.ffi_ptr()
+    }
+
     #[inline]
     pub fn create(ctx: &Context) -> io::Result<Self> {
         // SAFETY: ffi
@@ -38,8 +90,12 @@ impl CompChannel {
         Ok(Self(owner))
     }
 
+    /// Waits for a completion event
+    ///
+    /// Returns `None` if the completion queue of the event is being destroyed concurrently.
+    /// Such an event is acknowledged here and should be skipped by the caller.
     #[inline]
-    pub fn wait_cq_event(&self) -> io::Result<CompletionQueue> {
+    pub fn wait_cq_event(&self) -> io::Result<Option<CompletionQueue>> {
         let cc = self.ffi_ptr();
         let mut cq: *mut C::ibv_cq = ptr::null_mut();
         let mut cq_context: *mut c_void = ptr::null_mut();
@@ -54,8 +110,67 @@ impl CompChannel {
         // SAFETY:
         // 1. the cq is associated with the cc
         // 2. the cc is holding a weak reference to the cq
-        // 3. here may panic because the cq may have been destroyed
-        unsafe { Ok(CompletionQueue::from_cq_context(cq_context)) }
+        unsafe { Ok(take_event(cq, cq_context)) }
+    }
+
+    /// Gets a completion event without blocking
+    ///
+    /// Returns `None` if there is no event.
+    /// The events of completion queues which are being destroyed are skipped.
+    /// The channel must be in non-blocking mode, see [`CompChannel::set_nonblocking`].
+    #[inline]
+    pub fn try_wait_cq_event(&self) -> io::Result<Option<CompletionQueue>> {
+        let cc = self.ffi_ptr();
+        loop {
+            let mut cq: *mut C::ibv_cq = ptr::null_mut();
+            let mut cq_context: *mut c_void = ptr::null_mut();
+            // SAFETY: ffi
+            unsafe {
+                let ret =
+                    C::ibv_get_cq_event(cc, ptr::addr_of_mut!(cq), ptr::addr_of_mut!(cq_context));
+                if ret != 0 {
+                    let err = last_error();
+                    if err.kind() == io::ErrorKind::WouldBlock {
+                        return Ok(None);
+                    }
+                    return Err(err);
+                }
+                debug_assert_eq!((*cq).cq_context, cq_context);
+            }
+            // SAFETY: same as `wait_cq_event`
+            if let Some(cq) = unsafe { take_event(cq, cq_context) } {
+                return Ok(Some(cq));
+            }
+        }
+    }
+
+    /// Moves the channel into or out of non-blocking mode
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        // SAFETY: ffi
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 {
+                return Err(last_error());
+            }
+            let flags = if nonblocking {
+                flags | libc::O_NONBLOCK
+            } else {
+                flags & !libc::O_NONBLOCK
+            };
+            if libc::fcntl(fd, libc::F_SETFL, flags) < 0 {
+                return Err(last_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of completion queues associated with the channel
+    #[inline]
+    #[must_use]
+    pub fn cq_count(&self) -> usize {
+        self.0.cq_ref.lock().len()
     }
 
     pub(crate) fn add_cq_ref(&self, cq: Weak<cq::Owner>) {
@@ -67,6 +182,21 @@ impl CompChannel {
     }
 }
 
+/// Returns the completion queue of an event
+///
+/// If the last handle of the completion queue has been dropped, the event is acknowledged
+/// directly, because `ibv_destroy_cq` in the drop waits for all events to be acknowledged.
+///
+/// # SAFETY
+/// Same as `CompletionQueue::from_cq_context`. `cq` must be the queue of the event.
+unsafe fn take_event(cq: *mut C::ibv_cq, cq_context: *mut c_void) -> Option<CompletionQueue> {
+    let ans = CompletionQueue::from_cq_context(cq_context);
+    if ans.is_none() {
+        C::ibv_ack_cq_events(cq, 1);
+    }
+    ans
+}
+
 impl AsRawFd for CompChannel {
     #[inline]
     fn as_raw_fd(&self) -> RawFd {
@@ -76,6 +206,14 @@ impl AsRawFd for CompChannel {
     }
 }
 
+impl AsFd for CompChannel {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // SAFETY: the fd is valid until the channel is destroyed
+        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+    }
+}
+
 struct Owner {
     cc: NonNull<C::ibv_comp_channel>,
 
@@ -100,7 +238,100 @@ impl Drop for Owner {
         unsafe {
             let cc = self.ffi_ptr();
             let ret = C::ibv_destroy_comp_channel(cc);
-            assert_eq!(ret, 0);
+            check_drop(ret, "ibv_destroy_comp_channel");
+        }
+    }
+}
+
+/// A set of completion channels which can be waited at once.
+pub struct CompChannelSet {
+    epfd: OwnedFd,
+    channels: Vec<CompChannel>,
+}
+
+impl CompChannelSet {
+    #[inline]
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: ffi
+        let epfd = unsafe {
+            let fd = libc::epoll_create1(libc::EPOLL_CLOEXEC);
+            if fd < 0 {
+                return Err(last_error());
+            }
+            OwnedFd::from_raw_fd(fd)
+        };
+        Ok(Self {
+            epfd,
+            channels: Vec::new(),
+        })
+    }
+
+    #[inline]
+    pub fn insert(&mut self, cc: &CompChannel) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN.numeric_cast(),
+            u64: self.channels.len().numeric_cast(),
+        };
+        // SAFETY: ffi
+        let ret = unsafe {
+            let epfd = self.epfd.as_raw_fd();
+            let event = ptr::addr_of_mut!(event);
+            libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, cc.as_raw_fd(), event)
+        };
+        if ret != 0 {
+            return Err(last_error());
+        }
+        self.channels.push(cc.clone());
+        Ok(())
+    }
+
+    /// Waits for completion events on all channels in the set.
+    ///
+    /// Returns the completion queues which have received an event.
+    /// Waits forever if `timeout` is `None`.
+    /// Returns an empty vector if the timeout expires.
+    /// The timeout is rounded up to milliseconds.
+    ///
+    /// The events of all ready channels are taken before returning, since each one must be
+    /// acknowledged by the caller. If a channel fails, the error is returned only when no
+    /// completion queue is taken; otherwise the taken queues are returned and a persistent
+    /// error shows up in the next wait.
+    #[inline]
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<Vec<CompletionQueue>> {
+        let timeout: c_int = match timeout {
+            // a sub-millisecond timeout must not become a busy poll
+            Some(t) => c_int::try_from(t.as_nanos().div_ceil(1_000_000)).unwrap_or(c_int::MAX),
+            None => -1,
+        };
+
+        let mut events: Vec<libc::epoll_event> = Vec::with_capacity(self.channels.len().max(1));
+
+        // SAFETY: ffi
+        unsafe {
+            let epfd = self.epfd.as_raw_fd();
+            let max_events: c_int = events.capacity().numeric_cast();
+            let ret = libc::epoll_wait(epfd, events.as_mut_ptr(), max_events, timeout);
+            if ret < 0 {
+                return Err(last_error());
+            }
+            events.set_len(ret.numeric_cast());
+        }
+
+        let mut cqs = Vec::with_capacity(events.len());
+        let mut first_err = None;
+        for event in &events {
+            let index: usize = event.u64.numeric_cast();
+            match self.channels[index].wait_cq_event() {
+                Ok(Some(cq)) => cqs.push(cq),
+                Ok(None) => {}
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        match first_err {
+            Some(err) if cqs.is_empty() => Err(err),
+            _ => Ok(cqs),
         }
     }
 }
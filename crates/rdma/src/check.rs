@@ -0,0 +1,53 @@
+//! Buffer content validation
+//!
+//! A sender fills its buffer with [`fill_pattern`] and the receiver checks the
+//! received bytes with [`verify_pattern`] using the same seed.
+
+/// Fills `buf` with a pseudo-random pattern derived from `seed`
+#[inline]
+pub fn fill_pattern(buf: &mut [u8], seed: u64) {
+    for (dst, src) in buf.iter_mut().zip(pattern(seed)) {
+        *dst = src;
+    }
+}
+
+/// Checks that `buf` contains the pattern derived from `seed`
+///
+/// # Errors
+/// Returns the offset of the first mismatched byte
+#[inline]
+pub fn verify_pattern(buf: &[u8], seed: u64) -> Result<(), usize> {
+    match buf.iter().zip(pattern(seed)).position(|(&x, y)| x != y) {
+        Some(offset) => Err(offset),
+        None => Ok(()),
+    }
+}
+
+/// splitmix64
+fn pattern(seed: u64) -> impl Iterator<Item = u8> {
+    let mut state = seed;
+    let words = std::iter::from_fn(move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Some((z ^ (z >> 31)).to_le_bytes())
+    });
+    words.flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_roundtrip() {
+        let mut buf = vec![0u8; 1027];
+        fill_pattern(&mut buf, 42);
+        assert_eq!(verify_pattern(&buf, 42), Ok(()));
+        assert!(verify_pattern(&buf, 43).is_err());
+
+        buf[100] ^= 1;
+        assert_eq!(verify_pattern(&buf, 42), Err(100));
+    }
+}
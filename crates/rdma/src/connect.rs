@@ -0,0 +1,216 @@
+//! Connection helpers
+
+use crate::ah::AddressHandle;
+use crate::device::{Gid, GidEntry, LinkLayer, Mtu, PortAttr};
+use crate::error::custom_error;
+use crate::mr::AccessFlags;
+use crate::psn::random_psn;
+use crate::qp::{ModifyOptions, QueryOptions, QueuePair, QueuePairState};
+
+use std::io;
+
+use numeric_cast::NumericCast;
+
+/// The address of a queue pair which is exchanged with the peer before connecting
+///
+/// Under the `serde` feature, it can be serialized for the handshake.
+/// The encoding is up to the serializer, which should use a fixed byte order
+/// if the peers may have different native endianness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionInfo {
+    /// The queue pair number
+    pub qpn: u32,
+    /// The initial packet sequence number of the send queue
+    pub psn: u32,
+    /// The LID of the port, which is zero on Ethernet
+    pub lid: u16,
+    /// The GID which the queue pair uses
+    pub gid: Gid,
+    /// The index of `gid` in the GID table of the port
+    pub gid_index: u32,
+    /// The active MTU of the port
+    ///
+    /// The path MTU of a connection is the minimum of both sides.
+    pub mtu: Mtu,
+}
+
+impl ConnectionInfo {
+    /// Collects the local information of `qp` with a random initial PSN
+    #[inline]
+    #[must_use]
+    pub fn from_local(
+        qp: &QueuePair,
+        port_attr: &PortAttr,
+        gid_entry: &GidEntry,
+        gid_index: u32,
+    ) -> Self {
+        Self {
+            qpn: qp.qp_num(),
+            psn: random_psn(),
+            lid: port_attr.lid(),
+            gid: gid_entry.gid(),
+            gid_index,
+            mtu: port_attr.active_mtu(),
+        }
+    }
+
+    /// Returns the path MTU which is negotiated with `remote`
+    #[inline]
+    #[must_use]
+    pub fn path_mtu(&self, remote: &Self) -> Mtu {
+        self.mtu.min(remote.mtu)
+    }
+}
+
+fn local_info(qp: &QueuePair, port_num: u8, gid_index: u32) -> io::Result<ConnectionInfo> {
+    let ctx = qp.context();
+
+    let port_attr = PortAttr::query(ctx, port_num)?;
+    if port_attr.link_layer() != LinkLayer::Ethernet && port_attr.lid() == 0 {
+        return Err(custom_error("can not get local lid"));
+    }
+
+    let gid_entry = GidEntry::query(ctx, port_num.into(), gid_index)?;
+
+    Ok(ConnectionInfo::from_local(
+        qp, &port_attr, &gid_entry, gid_index,
+    ))
+}
+
+/// Connects two RC queue pairs in the same process
+///
+/// Both queue pairs must be in the `Reset` or `Initialize` state.
+/// They are driven through `Initialize`, `ReadyToReceive` and `ReadyToSend`,
+/// and allow remote read and write afterwards.
+///
+/// It is recommended to move the queue pairs to `Initialize` with [`rc_initialize`]
+/// and post receives before connecting them, so that no message arrives before
+/// a receive is posted.
+#[inline]
+pub fn connect_local(a: &QueuePair, b: &QueuePair, port_num: u8, gid_index: u32) -> io::Result<()> {
+    let a_info = local_info(a, port_num, gid_index)?;
+    let b_info = local_info(b, port_num, gid_index)?;
+
+    for (qp, local, remote) in [(a, &a_info, &b_info), (b, &b_info, &a_info)] {
+        rc_connect(qp, local, remote, port_num)?;
+    }
+
+    Ok(())
+}
+
+/// Reconnects two RC queue pairs in the same process after an error
+///
+/// This is the recovery flow after a completion with an error such as `RETRY_EXC_ERR`,
+/// which moves the queue pair to `Error`. Both queue pairs are moved to `Reset`
+/// and connected again with fresh PSNs, so that no stale packet of the previous
+/// connection is accepted.
+///
+/// Resetting discards the outstanding work requests without completions.
+/// To reclaim their buffers, move the queue pairs to `Error` with [`flush`]
+/// and drain the completion queues before calling this function.
+#[inline]
+pub fn recover_local(a: &QueuePair, b: &QueuePair, port_num: u8, gid_index: u32) -> io::Result<()> {
+    reset(a)?;
+    reset(b)?;
+    connect_local(a, b, port_num, gid_index)
+}
+
+/// Moves a queue pair to `Error`
+///
+/// The outstanding work requests are completed with the flush error status.
+#[inline]
+pub fn flush(qp: &QueuePair) -> io::Result<()> {
+    let mut options = ModifyOptions::default();
+    options.qp_state(QueuePairState::Error);
+    qp.modify(options)
+}
+
+/// Moves a queue pair to `Reset`
+///
+/// The queue pair can be connected again afterwards.
+#[inline]
+pub fn reset(qp: &QueuePair) -> io::Result<()> {
+    let mut options = ModifyOptions::default();
+    options.qp_state(QueuePairState::Reset);
+    qp.modify(options)
+}
+
+/// Moves a RC queue pair from `Reset` to `Initialize`
+///
+/// The queue pair allows remote read and write.
+/// Receives can be posted after this step.
+#[inline]
+pub fn rc_initialize(qp: &QueuePair, port_num: u8) -> io::Result<()> {
+    let mut options = ModifyOptions::default();
+    options
+        .qp_state(QueuePairState::Initialize)
+        .pkey_index(0)
+        .port_num(port_num)
+        .qp_access_flags(AccessFlags::REMOTE_READ | AccessFlags::REMOTE_WRITE);
+    qp.modify(options)
+}
+
+/// Connects a RC queue pair to a remote peer
+///
+/// `local` must be the information of `qp` which is sent to the peer,
+/// and `remote` the information which is received from it.
+/// The queue pair must be in the `Reset` or `Initialize` state.
+/// It is driven through `Initialize`, `ReadyToReceive` and `ReadyToSend`,
+/// and allows remote read and write afterwards.
+#[inline]
+pub fn rc_connect(
+    qp: &QueuePair,
+    local: &ConnectionInfo,
+    remote: &ConnectionInfo,
+    port_num: u8,
+) -> io::Result<()> {
+    let state = {
+        let mut options = QueryOptions::default();
+        options.qp_state();
+        qp.query(options)?.qp_state()
+    };
+    match state {
+        Some(QueuePairState::Reset) => rc_initialize(qp, port_num)?,
+        Some(QueuePairState::Initialize) => {}
+        _ => {
+            return Err(custom_error(
+                "the queue pair must be in the reset or initialize state",
+            ))
+        }
+    }
+
+    {
+        let mut ah_attr = AddressHandle::options();
+        ah_attr.dest_lid(remote.lid).port_num(port_num);
+
+        if remote.gid.interface_id() != 0 {
+            ah_attr.roce(remote.gid, local.gid_index.numeric_cast());
+        }
+
+        let mut options = ModifyOptions::default();
+        options
+            .qp_state(QueuePairState::ReadyToReceive)
+            .path_mtu(local.path_mtu(remote))
+            .dest_qp_num(remote.qpn)
+            .rq_psn(remote.psn)
+            .max_dest_rd_atomic(1)
+            .min_rnr_timer(12)
+            .ah_attr(ah_attr);
+        qp.modify(options)?;
+    }
+
+    {
+        let mut options = ModifyOptions::default();
+        options
+            .qp_state(QueuePairState::ReadyToSend)
+            .timeout(14)
+            .retry_cnt(7)
+            .rnr_retry(7)
+            .sq_psn(local.psn)
+            .max_rd_atomic(1);
+        qp.modify(options)?;
+    }
+
+    Ok(())
+}
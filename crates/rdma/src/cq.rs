@@ -1,17 +1,19 @@
 use crate::bindings as C;
 use crate::cc::CompChannel;
 use crate::ctx::Context;
-use crate::error::{create_resource, from_errno};
-use crate::utils::{bool_to_c_int, ptr_as_mut};
-use crate::wc::WorkCompletion;
+use crate::device::DeviceAttr;
+use crate::error::{check_drop, create_resource, custom_error, from_errno, report_leak};
+use crate::utils::{bool_to_c_int, ptr_as_mut, ptr_to_addr};
+use crate::wc::{CompletionError, Opcode, WorkCompletion, WorkCompletionError};
 
+use std::marker::PhantomData;
 use std::mem::{self, ManuallyDrop, MaybeUninit};
 use std::os::raw::{c_int, c_uint, c_void};
 use std::ptr::NonNull;
-use std::sync::atomic::AtomicU32;
 use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicU32, AtomicUsize};
 use std::sync::{Arc, Weak};
-use std::{io, slice};
+use std::{io, ptr, slice};
 
 use numeric_cast::NumericCast;
 
@@ -23,6 +25,25 @@ impl CompletionQueue {
         self.0.ffi_ptr()
     }
 
+    /// Returns the underlying `ibv_cq` pointer
+    ///
+    /// The pointer is valid as long as the completion queue is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_cq {
+        C::ibv_cq_ex_to_cq(self.ffi_ptr())
+    }
+
+    /// Returns the underlying `ibv_cq_ex` pointer
+    ///
+    /// The completion queue is always created by `ibv_create_cq_ex`.
+    #[inline]
+    #[must_use]
+    pub fn as_raw_ex(&self) -> *mut C::ibv_cq_ex {
+        self.ffi_ptr()
+    }
+
     #[inline]
     #[must_use]
     pub fn options() -> CompletionQueueOptions {
@@ -37,11 +58,22 @@ impl CompletionQueue {
 
             let mut cq_attr: C::ibv_cq_init_attr_ex = mem::zeroed();
             cq_attr.cqe = options.cqe.numeric_cast();
+            cq_attr.wc_flags = C::IBV_WC_STANDARD_FLAGS.into();
+            if options.completion_timestamp {
+                cq_attr.wc_flags |= u64::from(C::IBV_WC_EX_WITH_COMPLETION_TIMESTAMP);
+            }
 
             if let Some(ref cc) = options.channel {
                 cq_attr.channel = cc.ffi_ptr();
             }
 
+            if let Some(comp_vector) = options.comp_vector {
+                if comp_vector >= ctx.num_comp_vectors() {
+                    return Err(custom_error("invalid completion vector"));
+                }
+                cq_attr.comp_vector = comp_vector;
+            }
+
             let cq = create_resource(
                 || C::ibv_create_cq_ex(context, &mut cq_attr),
                 || "failed to create completion queue",
@@ -51,7 +83,10 @@ impl CompletionQueue {
                 cq,
                 user_data: options.user_data,
                 comp_events_completed: AtomicU32::new(0),
-                _ctx: ctx.clone(),
+                qp_count: AtomicU32::new(0),
+                completion_timestamp: options.completion_timestamp,
+                poller: AtomicUsize::new(0),
+                ctx: ctx.clone(),
                 cc: options.channel,
             })
         };
@@ -70,17 +105,15 @@ impl CompletionQueue {
         Ok(Self(owner))
     }
 
-    /// # Panics
-    /// + if the completion queue has been destroyed
+    /// Returns `None` if the last handle of the completion queue has been dropped
     ///
     /// # SAFETY
     /// 1. `cq_context` must come from the pointee of `CompletionQueue::ffi_ptr`
     /// 2. there must be at least one weak reference to the completion queue owner
-    pub(crate) unsafe fn from_cq_context(cq_context: *mut c_void) -> Self {
+    pub(crate) unsafe fn from_cq_context(cq_context: *mut c_void) -> Option<Self> {
         let owner_ptr: *const Owner = cq_context.cast();
         let weak = ManuallyDrop::new(Weak::from_raw(owner_ptr));
-        let owner = Weak::upgrade(&weak).expect("the completion queue has been destroyed");
-        Self(owner)
+        Weak::upgrade(&weak).map(Self)
     }
 
     #[inline]
@@ -112,16 +145,80 @@ impl CompletionQueue {
         self.req_notify(true)
     }
 
+    /// Sets the CQ moderation, which delays the completion event
+    /// until `cq_count` completions are generated or `cq_period_us` microseconds have passed.
+    ///
+    /// Returns `EOPNOTSUPP` if the device does not support CQ moderation,
+    /// or `EINVAL` if the values exceed the device limits.
+    #[inline]
+    pub fn modify_moderation(&self, cq_count: u16, cq_period_us: u16) -> io::Result<()> {
+        let device_attr = DeviceAttr::query(&self.0.ctx)?;
+        let max_count = device_attr.max_cq_moderation_count();
+        let max_period = device_attr.max_cq_moderation_period();
+        if max_count == 0 && max_period == 0 {
+            return Err(from_errno(libc::EOPNOTSUPP));
+        }
+        if cq_count > max_count || cq_period_us > max_period {
+            return Err(from_errno(libc::EINVAL));
+        }
+
+        // SAFETY: ffi
+        let ret = unsafe {
+            let mut attr: C::ibv_modify_cq_attr = mem::zeroed();
+            attr.attr_mask = C::IBV_CQ_ATTR_MODERATE;
+            attr.moderate.cq_count = cq_count;
+            attr.moderate.cq_period = cq_period_us;
+            C::ibv_modify_cq(C::ibv_cq_ex_to_cq(self.ffi_ptr()), ptr::addr_of_mut!(attr))
+        };
+        if ret != 0 {
+            return Err(from_errno(ret));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn add_qp_ref(&self) {
+        self.0.qp_count.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn del_qp_ref(&self) {
+        self.0.qp_count.fetch_sub(1, Relaxed);
+    }
+
     #[inline]
     pub fn ack_cq_events(&self, cnt: u32) {
         self.0.comp_events_completed.fetch_add(cnt, Relaxed);
     }
 
+    /// Polls up to `buf.len()` completions and returns the written ones
+    ///
+    /// This never blocks. The lowest latency is achieved by a completion queue
+    /// which is created without a [channel](CompletionQueueOptions::channel)
+    /// and is polled in a busy loop until the expected completions arrive:
+    ///
+    /// ```no_run
+    /// # fn f(cq: &rdma::cq::CompletionQueue) -> std::io::Result<()> {
+    /// let wc = loop {
+    ///     if let Some(wc) = cq.poll_one()? {
+    ///         break wc;
+    ///     }
+    /// };
+    /// # Ok(()) }
+    /// ```
+    ///
+    /// A busy loop keeps a core fully occupied. Use a completion channel
+    /// when the latency of a wakeup is acceptable.
+    ///
+    /// It must not be called while the thread holds an [`ExtendedPoll`] of the completion queue.
     #[inline]
     pub fn poll<'wc>(
         &self,
         buf: &'wc mut [MaybeUninit<WorkCompletion>],
     ) -> io::Result<&'wc mut [WorkCompletion]> {
+        debug_assert_ne!(
+            self.0.poller.load(Relaxed),
+            current_thread_token(),
+            "nested poll of a completion queue which is in an extended poll"
+        );
         // SAFETY: ffi
         unsafe {
             let num_entries: c_int = buf.len().numeric_cast();
@@ -136,15 +233,249 @@ impl CompletionQueue {
             Ok(slice::from_raw_parts_mut(data, len))
         }
     }
+
+    /// Polls exactly one completion, returns `None` if the completion queue is empty
+    #[inline]
+    pub fn poll_one(&self) -> io::Result<Option<WorkCompletion>> {
+        let mut buf = [MaybeUninit::<WorkCompletion>::uninit()];
+        let len = self.poll(&mut buf)?.len();
+        // SAFETY: the entry is written by the driver if `len` is 1
+        Ok((len == 1).then(|| unsafe { buf[0].assume_init_read() }))
+    }
+
+    /// Polls the completion queue and checks the status of each completion
+    ///
+    /// A failed completion is yielded as an error which still carries its `wr_id`.
+    #[inline]
+    pub fn poll_checked<'wc>(
+        &self,
+        buf: &'wc mut [MaybeUninit<WorkCompletion>],
+    ) -> io::Result<impl Iterator<Item = Result<&'wc WorkCompletion, CompletionError>>> {
+        let wcs = self.poll(buf)?;
+        Ok(wcs.iter().map(WorkCompletion::check))
+    }
+
+    /// Starts polling the completion queue with the extended API
+    ///
+    /// Returns `None` if the completion queue is empty.
+    /// Otherwise the returned view points to the first completion.
+    ///
+    /// The poll is exclusive: while the view is alive, the thread must not call
+    /// `start_poll` or [`poll`](Self::poll) on the same completion queue.
+    /// The driver may lock the completion queue until the view is dropped,
+    /// so a nested call deadlocks. Debug builds panic on a nested call instead.
+    /// Other threads which poll the completion queue wait for the view to be dropped.
+    #[inline]
+    pub fn start_poll(&self) -> io::Result<Option<ExtendedPoll<'_>>> {
+        let token = current_thread_token();
+        debug_assert_ne!(
+            self.0.poller.load(Relaxed),
+            token,
+            "nested poll of a completion queue which is in an extended poll"
+        );
+
+        let cq = self.ffi_ptr();
+        // SAFETY: ffi
+        let ret = unsafe {
+            let mut attr: C::ibv_poll_cq_attr = mem::zeroed();
+            C::ibv_start_poll(cq, ptr::addr_of_mut!(attr))
+        };
+        match ret {
+            0 => {
+                self.0.poller.store(token, Relaxed);
+                Ok(Some(ExtendedPoll {
+                    cq,
+                    completion_timestamp: self.0.completion_timestamp,
+                    poller: &self.0.poller,
+                    _marker: PhantomData,
+                }))
+            }
+            libc::ENOENT => Ok(None),
+            _ => Err(from_errno(ret)),
+        }
+    }
+}
+
+/// A reusable buffer of work completions for [`CompletionQueue::poll`]
+///
+/// The buffer is allocated once and is never zeroed.
+/// Each poll overwrites the front of the buffer and returns only the written completions.
+pub struct CompletionBuffer {
+    buf: Vec<MaybeUninit<WorkCompletion>>,
+}
+
+impl CompletionBuffer {
+    /// Creates a buffer which holds up to `capacity` completions
+    #[inline]
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        let buf = (0..capacity).map(|_| MaybeUninit::uninit()).collect();
+        Self { buf }
+    }
+
+    /// Returns the maximum number of completions of one poll
+    #[inline]
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Polls `cq` and returns the completions which are written into the buffer
+    #[inline]
+    pub fn poll(&mut self, cq: &CompletionQueue) -> io::Result<&mut [WorkCompletion]> {
+        cq.poll(&mut self.buf)
+    }
+
+    /// Polls `cq` and checks the status of each completion, see [`CompletionQueue::poll_checked`]
+    #[inline]
+    pub fn poll_checked(
+        &mut self,
+        cq: &CompletionQueue,
+    ) -> io::Result<impl Iterator<Item = Result<&WorkCompletion, CompletionError>>> {
+        cq.poll_checked(&mut self.buf)
+    }
+}
+
+/// A view of the current completion of an extended poll
+///
+/// The fields are read through the reader functions of the driver.
+/// The poll is ended when the view is dropped.
+///
+/// Until then, the completion queue must not be polled again by the same thread,
+/// see [`CompletionQueue::start_poll`].
+pub struct ExtendedPoll<'cq> {
+    cq: *mut C::ibv_cq_ex,
+    /// whether the driver provides `read_completion_ts`
+    completion_timestamp: bool,
+    poller: &'cq AtomicUsize,
+    _marker: PhantomData<&'cq CompletionQueue>,
+}
+
+impl ExtendedPoll<'_> {
+    /// Advances to the next completion
+    ///
+    /// Returns `false` if there are no more completions.
+    #[inline]
+    pub fn advance(&mut self) -> io::Result<bool> {
+        // SAFETY: ffi
+        let ret = unsafe { C::ibv_next_poll(self.cq) };
+        match ret {
+            0 => Ok(true),
+            libc::ENOENT => Ok(false),
+            _ => Err(from_errno(ret)),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn wr_id(&self) -> u64 {
+        // SAFETY: the current completion is valid until the next poll
+        unsafe { (*self.cq).wr_id }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn status(&self) -> u32 {
+        // SAFETY: the current completion is valid until the next poll
+        unsafe { (*self.cq).status }.numeric_cast()
+    }
+
+    /// Checks the status of the current completion
+    ///
+    /// # Errors
+    /// + the [`WorkCompletionError`] of a failed completion
+    #[inline]
+    pub fn check(&self) -> Result<(), WorkCompletionError> {
+        WorkCompletionError::result(self.status())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn opcode(&self) -> Opcode {
+        // SAFETY: ffi
+        Opcode::from_c_uint(unsafe { C::ibv_wc_read_opcode(self.cq) })
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn vendor_err(&self) -> u32 {
+        // SAFETY: ffi
+        unsafe { C::ibv_wc_read_vendor_err(self.cq) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn byte_len(&self) -> u32 {
+        // SAFETY: ffi
+        unsafe { C::ibv_wc_read_byte_len(self.cq) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn qp_num(&self) -> u32 {
+        // SAFETY: ffi
+        unsafe { C::ibv_wc_read_qp_num(self.cq) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn wc_flags(&self) -> u32 {
+        // SAFETY: ffi
+        unsafe { C::ibv_wc_read_wc_flags(self.cq) }
+    }
+
+    /// Returns the immediate data in host byte order
+    #[inline]
+    #[must_use]
+    pub fn imm_data(&self) -> Option<u32> {
+        self.imm_data_be().map(u32::from_be)
+    }
+
+    /// Returns the completion timestamp in HCA clock ticks
+    ///
+    /// Returns `None` if the completion queue is not created with
+    /// [`CompletionQueueOptions::completion_timestamp`].
+    #[inline]
+    #[must_use]
+    pub fn completion_ts(&self) -> Option<u64> {
+        // SAFETY: ffi, the reader is set if the cq is created with the timestamp flag
+        self.completion_timestamp
+            .then(|| unsafe { C::ibv_wc_read_completion_ts(self.cq) })
+    }
+
+    /// Returns the immediate data in network byte order
+    #[inline]
+    #[must_use]
+    pub fn imm_data_be(&self) -> Option<u32> {
+        let has_imm = self.wc_flags() & C::IBV_WC_WITH_IMM != 0;
+        // SAFETY: ffi
+        has_imm.then(|| unsafe { C::ibv_wc_read_imm_data(self.cq) })
+    }
+}
+
+impl Drop for ExtendedPoll<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // cleared before the driver unlocks the cq for other threads
+        self.poller.store(0, Relaxed);
+        // SAFETY: ffi
+        unsafe { C::ibv_end_poll(self.cq) }
+    }
 }
 
 pub(crate) struct Owner {
     cq: NonNull<C::ibv_cq_ex>,
     user_data: usize,
     comp_events_completed: AtomicU32,
+    /// the number of queue pairs which reference this completion queue
+    qp_count: AtomicU32,
+    /// whether the cq is created with `IBV_WC_EX_WITH_COMPLETION_TIMESTAMP`
+    completion_timestamp: bool,
+    /// the token of the thread which is in an extended poll, or zero
+    poller: AtomicUsize,
 
     cc: Option<CompChannel>,
-    _ctx: Context,
+    ctx: Context,
 }
 
 /// SAFETY: owned type
@@ -158,34 +489,144 @@ impl Owner {
     }
 }
 
+impl Owner {
+    /// Keeps the allocation alive for a leaked `ibv_cq`
+    ///
+    /// `cq_context` still points to the allocation, and the channel may still deliver events
+    /// of the queue, so its weak reference stays in the channel and an extra one is forgotten.
+    /// The events are acknowledged by `take_event` since the upgrade fails.
+    fn leak_allocation(&self) {
+        let owner_ptr: *const Self = self;
+        // SAFETY: the allocation is alive during the drop, and `owner_ptr` is `Arc::as_ptr`
+        let weak = ManuallyDrop::new(unsafe { Weak::from_raw(owner_ptr) });
+        mem::forget(Weak::clone(&weak));
+    }
+}
+
 impl Drop for Owner {
     fn drop(&mut self) {
-        if let Some(ref cc) = self.cc {
-            assert!(cc.del_cq_ref(self));
+        let qp_count = self.qp_count.load(Relaxed);
+        if qp_count != 0 {
+            // destroying it would fail with `EBUSY`
+            let reason = format!("still referenced by {qp_count} queue pairs");
+            report_leak("completion queue", &reason);
+            self.leak_allocation();
+            return;
         }
 
         // SAFETY: ffi
-        unsafe {
+        let ret = unsafe {
             let cq = C::ibv_cq_ex_to_cq(self.ffi_ptr());
 
             let comp_ack: c_uint = self.comp_events_completed.load(Relaxed).numeric_cast();
             // if the number overflows, the behavior is unspecified
             C::ibv_ack_cq_events(cq, comp_ack);
 
-            let ret = C::ibv_destroy_cq(cq);
-            assert_eq!(ret, 0);
+            C::ibv_destroy_cq(cq)
         };
+        check_drop(ret, "ibv_destroy_cq");
+        if ret != 0 {
+            // the `ibv_cq` is still alive
+            self.leak_allocation();
+            return;
+        }
+
+        if let Some(ref cc) = self.cc {
+            assert!(cc.del_cq_ref(self));
+        }
     }
 }
 
+/// Returns a nonzero token which is unique among the running threads
+fn current_thread_token() -> usize {
+    thread_local!(static TOKEN: u8 = const { 0 });
+    TOKEN.with(|token| ptr_to_addr(token))
+}
+
+/// A completion timestamp in HCA clock ticks
+///
+/// The hardware counter has a limited width, which is given by
+/// [`DeviceAttr::completion_timestamp_mask`](crate::device::DeviceAttr::completion_timestamp_mask).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    ticks: u64,
+    mask: u64,
+}
+
+impl Timestamp {
+    #[inline]
+    #[must_use]
+    pub const fn new(ticks: u64, mask: u64) -> Self {
+        Self {
+            ticks: ticks & mask,
+            mask,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn ticks(self) -> u64 {
+        self.ticks
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn mask(self) -> u64 {
+        self.mask
+    }
+
+    /// Returns the ticks elapsed since `earlier`, assuming the counter wrapped around at most once
+    #[inline]
+    #[must_use]
+    pub const fn ticks_since(self, earlier: Self) -> u64 {
+        self.ticks.wrapping_sub(earlier.ticks) & self.mask
+    }
+
+    /// Converts the ticks to nanoseconds
+    ///
+    /// # Panics
+    /// + if `core_clock_khz` is zero
+    #[inline]
+    #[must_use]
+    pub fn to_nanos(self, core_clock_khz: u64) -> u64 {
+        ticks_to_nanos(self.ticks, core_clock_khz)
+    }
+
+    /// Returns the nanoseconds elapsed since `earlier`, handling the wraparound of the counter
+    ///
+    /// # Panics
+    /// + if `core_clock_khz` is zero
+    #[inline]
+    #[must_use]
+    pub fn nanos_since(self, earlier: Self, core_clock_khz: u64) -> u64 {
+        ticks_to_nanos(self.ticks_since(earlier), core_clock_khz)
+    }
+}
+
+fn ticks_to_nanos(ticks: u64, core_clock_khz: u64) -> u64 {
+    assert!(core_clock_khz != 0, "unknown core clock");
+    let nanos = u128::from(ticks) * 1_000_000 / u128::from(core_clock_khz);
+    u64::try_from(nanos).unwrap_or(u64::MAX)
+}
+
 #[derive(Default)]
 pub struct CompletionQueueOptions {
     cqe: usize,
     user_data: usize,
     channel: Option<CompChannel>,
+    comp_vector: Option<u32>,
+    completion_timestamp: bool,
 }
 
 impl CompletionQueueOptions {
+    /// Sets the minimum number of entries of the completion queue
+    ///
+    /// The capacity must be at least the total number of outstanding work requests
+    /// which generate completions, counting both the send and the receive queues
+    /// of all queue pairs attached to the completion queue.
+    /// Otherwise the completion queue may overrun. It then enters the error state,
+    /// reports [`CqError`](crate::event::AsyncEventType::CqError) as an asynchronous event
+    /// and stops reporting completions.
     #[inline]
     pub fn cqe(&mut self, cqe: usize) -> &mut Self {
         self.cqe = cqe;
@@ -201,4 +642,77 @@ impl CompletionQueueOptions {
         self.channel = Some(cc.clone());
         self
     }
+    /// Sets the completion vector which signals the completion events.
+    ///
+    /// `comp_vector` must be in the range `0..ctx.num_comp_vectors()`.
+    #[inline]
+    pub fn comp_vector(&mut self, comp_vector: u32) -> &mut Self {
+        self.comp_vector = Some(comp_vector);
+        self
+    }
+    /// Enables completion timestamps, which are read by [`ExtendedPoll::completion_ts`].
+    #[inline]
+    pub fn completion_timestamp(&mut self, enable: bool) -> &mut Self {
+        self.completion_timestamp = enable;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_wraparound() {
+        let mask = 0xffff_ffff;
+        let t0 = Timestamp::new(0xffff_fff0, mask);
+        let t1 = Timestamp::new(0x1_0000_0010, mask);
+        assert_eq!(t1.ticks(), 0x10);
+        assert_eq!(t1.ticks_since(t0), 0x20);
+
+        // 1 GHz
+        assert_eq!(t1.nanos_since(t0, 1_000_000), 0x20);
+        // 250 MHz
+        assert_eq!(Timestamp::new(250, mask).to_nanos(250_000), 1000);
+    }
+
+    #[test]
+    fn thread_token() {
+        let token = current_thread_token();
+        assert_ne!(token, 0);
+        assert_eq!(token, current_thread_token());
+        let other = std::thread::spawn(current_thread_token).join().unwrap();
+        assert_ne!(token, other);
+    }
+
+    #[test]
+    fn completion_ts_reader() {
+        static READS: AtomicU32 = AtomicU32::new(0);
+
+        unsafe extern "C" fn read_completion_ts(_: *mut C::ibv_cq_ex) -> u64 {
+            READS.fetch_add(1, Relaxed);
+            42
+        }
+        unsafe extern "C" fn end_poll(_: *mut C::ibv_cq_ex) {}
+
+        // SAFETY: POD ffi type
+        let mut cq: C::ibv_cq_ex = unsafe { mem::zeroed() };
+        cq.end_poll = Some(end_poll);
+
+        let poller = AtomicUsize::new(0);
+        let view = |cq: &mut C::ibv_cq_ex, completion_timestamp| ExtendedPoll {
+            cq,
+            completion_timestamp,
+            poller: &poller,
+            _marker: PhantomData,
+        };
+
+        // the reader is not set without the timestamp flag
+        assert_eq!(view(&mut cq, false).completion_ts(), None);
+        assert_eq!(READS.load(Relaxed), 0);
+
+        cq.read_completion_ts = Some(read_completion_ts);
+        assert_eq!(view(&mut cq, true).completion_ts(), Some(42));
+        assert_eq!(READS.load(Relaxed), 1);
+    }
 }
@@ -1,10 +1,17 @@
 use crate::bindings as C;
-use crate::device::Device;
-use crate::error::create_resource;
+use crate::cq::Timestamp;
+use crate::device::{Capabilities, Device, DeviceAttr, DeviceList};
+use crate::error::{check_drop, create_resource, from_errno};
 
-use std::io;
-use std::ptr::NonNull;
+use std::ffi::CStr;
+use std::mem;
+use std::path::Path;
+use std::ptr::{self, NonNull};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use std::{fmt, io};
+
+use numeric_cast::NumericCast;
 
 #[derive(Clone)]
 pub struct Context(Arc<Owner>);
@@ -14,6 +21,16 @@ impl Context {
         self.0.ffi_ptr()
     }
 
+    /// Returns the underlying `ibv_context` pointer
+    ///
+    /// The pointer is valid as long as the context is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_context {
+        self.ffi_ptr()
+    }
+
     #[inline]
     pub fn open(device: &Device) -> io::Result<Self> {
         // SAFETY: ffi
@@ -22,14 +39,216 @@ impl Context {
                 || C::ibv_open_device(device.ffi_ptr()),
                 || "failed to open device",
             )?;
-            Arc::new(Owner { ctx })
+            Arc::new(Owner { ctx, owned: true })
         };
         Ok(Self(owner))
     }
+
+    /// Opens the device whose uverbs character device is at `path`
+    ///
+    /// The path is compared against [`Device::path`] of the available devices.
+    ///
+    /// # Errors
+    /// + [`io::ErrorKind::NotFound`] if no available device has the path
+    #[inline]
+    pub fn open_path(path: &Path) -> io::Result<Self> {
+        let list = DeviceList::available()?;
+        let Some(dev) = list.iter().find(|dev| dev.path() == path) else {
+            let msg = format!("no rdma device at {}", path.display());
+            return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+        };
+        Self::open(dev)
+    }
+
+    /// Opens the available device named `name`
+    ///
+    /// # Errors
+    /// + [`OpenDeviceError::NoDevices`] if there is no available device
+    /// + [`OpenDeviceError::DeviceNotFound`] if no available device has the name
+    /// + [`OpenDeviceError::OpenFailed`] if the device list can not be read or the device can not be opened
+    #[inline]
+    pub fn open_by_name(name: &str) -> Result<Self, OpenDeviceError> {
+        let list = DeviceList::available().map_err(OpenDeviceError::OpenFailed)?;
+        if list.is_empty() {
+            return Err(OpenDeviceError::NoDevices);
+        }
+        let Some(dev) = list.iter().find(|dev| dev.name() == name) else {
+            return Err(OpenDeviceError::DeviceNotFound(name.to_owned()));
+        };
+        Self::open(dev).map_err(OpenDeviceError::OpenFailed)
+    }
+
+    /// Opens the first available device
+    ///
+    /// # Errors
+    /// See [`Context::open_by_name`].
+    #[inline]
+    pub fn open_first() -> Result<Self, OpenDeviceError> {
+        let list = DeviceList::available().map_err(OpenDeviceError::OpenFailed)?;
+        let Some(dev) = list.first() else {
+            return Err(OpenDeviceError::NoDevices);
+        };
+        Self::open(dev).map_err(OpenDeviceError::OpenFailed)
+    }
+
+    /// Wraps a context which is opened by other libraries
+    ///
+    /// If `owned` is true, the context is closed when the last handle is dropped.
+    /// Otherwise the caller keeps the control of its lifetime.
+    ///
+    /// # Safety
+    /// + `ctx` must be a valid context
+    /// + if `owned` is false, `ctx` must outlive all resources created by the returned handle
+    ///
+    /// # Panics
+    /// + if `ctx` is null
+    #[inline]
+    #[must_use]
+    pub unsafe fn from_raw(ctx: *mut C::ibv_context, owned: bool) -> Self {
+        let ctx = NonNull::new(ctx).expect("null context");
+        Self(Arc::new(Owner { ctx, owned }))
+    }
+
+    /// Returns the kernel name of the opened device
+    ///
+    /// # Panics
+    /// + if the device name is not a valid utf8 string
+    #[inline]
+    #[must_use]
+    pub fn device_name(&self) -> &str {
+        // SAFETY: ffi, the device outlives the context
+        let name = unsafe { CStr::from_ptr(C::ibv_get_device_name((*self.ffi_ptr()).device)) };
+        name.to_str().expect("non-utf8 device name")
+    }
+
+    /// Returns the number of completion vectors of the device.
+    ///
+    /// The valid completion vectors are `0..num_comp_vectors`.
+    #[inline]
+    #[must_use]
+    pub fn num_comp_vectors(&self) -> u32 {
+        let ctx = self.ffi_ptr();
+        // SAFETY: reading a immutable field of a concurrent ffi type
+        unsafe { (*ctx).num_comp_vectors }.numeric_cast()
+    }
+
+    /// Queries the device and returns an overview of its supported features
+    #[inline]
+    pub fn capabilities(&self) -> io::Result<Capabilities> {
+        Ok(DeviceAttr::query(self)?.capabilities())
+    }
+
+    /// Reads the current HCA clock together with the system realtime clock
+    ///
+    /// Returns `EOPNOTSUPP` if the provider does not implement `ibv_query_rt_values_ex`.
+    #[inline]
+    pub fn query_rt_values(&self) -> io::Result<RtValues> {
+        // SAFETY: ffi
+        unsafe {
+            let mut values: C::ibv_values_ex = mem::zeroed();
+            values.comp_mask = C::IBV_VALUES_MASK_RAW_CLOCK;
+            let ret = C::ibv_query_rt_values_ex(self.ffi_ptr(), ptr::addr_of_mut!(values));
+            let realtime = SystemTime::now();
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+            let raw_clock = &values.raw_clock;
+            let sec: u64 = raw_clock.tv_sec.numeric_cast();
+            let nsec: u64 = raw_clock.tv_nsec.numeric_cast();
+            Ok(RtValues {
+                raw_clock: sec.wrapping_mul(1_000_000_000).wrapping_add(nsec),
+                realtime,
+            })
+        }
+    }
+}
+
+/// An error of [`Context::open_by_name`] and [`Context::open_first`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OpenDeviceError {
+    /// There is no available rdma device
+    NoDevices,
+    /// No available rdma device has the name
+    DeviceNotFound(String),
+    /// Failed to list or open the device
+    OpenFailed(io::Error),
+}
+
+impl fmt::Display for OpenDeviceError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoDevices => write!(f, "no available rdma devices"),
+            Self::DeviceNotFound(name) => write!(f, "can not find rdma device with name: {name}"),
+            Self::OpenFailed(err) => write!(f, "failed to open rdma device: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for OpenDeviceError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::OpenFailed(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<OpenDeviceError> for io::Error {
+    #[inline]
+    fn from(err: OpenDeviceError) -> Self {
+        match err {
+            OpenDeviceError::OpenFailed(err) => err,
+            _ => io::Error::new(io::ErrorKind::NotFound, err),
+        }
+    }
+}
+
+/// A sample of the HCA clock and the system realtime clock taken at the same time
+#[derive(Debug, Clone, Copy)]
+pub struct RtValues {
+    raw_clock: u64,
+    realtime: SystemTime,
+}
+
+impl RtValues {
+    /// Returns the HCA clock in ticks
+    #[inline]
+    #[must_use]
+    pub fn raw_clock(&self) -> u64 {
+        self.raw_clock
+    }
+
+    /// Returns the system realtime clock which is read right after the HCA clock
+    #[inline]
+    #[must_use]
+    pub fn realtime(&self) -> SystemTime {
+        self.realtime
+    }
+
+    /// Converts a completion timestamp to the wall-clock time
+    ///
+    /// `completion` must be taken before this sample and
+    /// the counter must not wrap around more than once between them.
+    ///
+    /// Returns `None` if the result can not be represented by [`SystemTime`].
+    ///
+    /// # Panics
+    /// + if `core_clock_khz` is zero
+    #[inline]
+    #[must_use]
+    pub fn wall_clock(&self, completion: Timestamp, core_clock_khz: u64) -> Option<SystemTime> {
+        let now = Timestamp::new(self.raw_clock, completion.mask());
+        let nanos = now.nanos_since(completion, core_clock_khz);
+        self.realtime.checked_sub(Duration::from_nanos(nanos))
+    }
 }
 
 struct Owner {
     ctx: NonNull<C::ibv_context>,
+    owned: bool,
 }
 
 /// SAFETY: owned type
@@ -45,11 +264,14 @@ impl Owner {
 
 impl Drop for Owner {
     fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
         // SAFETY: ffi
         unsafe {
             let context = self.ffi_ptr();
             let ret = C::ibv_close_device(context);
-            assert_eq!(ret, 0);
+            check_drop(ret, "ibv_close_device");
         }
     }
 }
@@ -1,10 +1,12 @@
 use crate::bindings as C;
 use crate::ctx::Context;
 use crate::error::from_errno;
+use crate::utils::c_uint_to_u32;
 
 use std::io;
 use std::ptr;
 
+use numeric_cast::NumericCast;
 use rust_utils::boxed::BoxExt;
 
 pub struct DeviceAttr(Box<C::ibv_device_attr_ex>);
@@ -25,9 +27,256 @@ impl DeviceAttr {
         }
     }
 
+    /// Queries the device with the legacy `ibv_query_device`
+    ///
+    /// Only the basic attributes are filled.
+    /// The extended attributes read as zero, as if the device does not support them.
+    #[inline]
+    pub fn query_basic(ctx: &Context) -> io::Result<Self> {
+        // SAFETY: ffi
+        unsafe {
+            let mut device_attr = <Box<C::ibv_device_attr_ex>>::new_zeroed_();
+            let context = ctx.ffi_ptr();
+            let orig_attr = ptr::addr_of_mut!((*device_attr.as_mut_ptr()).orig_attr);
+            let ret = C::ibv_query_device(context, orig_attr);
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+            Ok(Self(Box::assume_init_(device_attr)))
+        }
+    }
+
     #[inline]
     #[must_use]
     pub fn physical_port_count(&self) -> u8 {
         self.0.orig_attr.phys_port_cnt
     }
+
+    #[inline]
+    #[must_use]
+    pub fn max_qp(&self) -> u32 {
+        self.0.orig_attr.max_qp.numeric_cast()
+    }
+
+    /// Returns the maximum number of outstanding work requests of a send or receive queue.
+    ///
+    /// It bounds [`QueuePairCapacity::max_send_wr`](crate::qp::QueuePairCapacity::max_send_wr)
+    /// and [`QueuePairCapacity::max_recv_wr`](crate::qp::QueuePairCapacity::max_recv_wr).
+    #[inline]
+    #[must_use]
+    pub fn max_qp_wr(&self) -> u32 {
+        self.0.orig_attr.max_qp_wr.numeric_cast()
+    }
+
+    /// Returns the maximum number of scatter/gather entries of a work request.
+    ///
+    /// It bounds [`QueuePairCapacity::max_send_sge`](crate::qp::QueuePairCapacity::max_send_sge)
+    /// and [`QueuePairCapacity::max_recv_sge`](crate::qp::QueuePairCapacity::max_recv_sge).
+    #[inline]
+    #[must_use]
+    pub fn max_sge(&self) -> u32 {
+        self.0.orig_attr.max_sge.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_cq(&self) -> u32 {
+        self.0.orig_attr.max_cq.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_mr(&self) -> u32 {
+        self.0.orig_attr.max_mr.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_pd(&self) -> u32 {
+        self.0.orig_attr.max_pd.numeric_cast()
+    }
+
+    /// Returns the maximum number of outstanding RDMA reads and atomics
+    /// which a queue pair can handle as the responder
+    ///
+    /// This is the upper bound of `max_dest_rd_atomic` of a queue pair.
+    #[inline]
+    #[must_use]
+    pub fn max_qp_rd_atom(&self) -> u32 {
+        self.0.orig_attr.max_qp_rd_atom.numeric_cast()
+    }
+
+    /// Returns the maximum number of outstanding RDMA reads and atomics
+    /// which a queue pair can initiate
+    ///
+    /// This is the upper bound of `max_rd_atomic` of a queue pair.
+    #[inline]
+    #[must_use]
+    pub fn max_qp_init_rd_atom(&self) -> u32 {
+        self.0.orig_attr.max_qp_init_rd_atom.numeric_cast()
+    }
+
+    /// Returns the maximum size of device memory in bytes.
+    ///
+    /// Returns zero if the device does not support device memory.
+    #[inline]
+    #[must_use]
+    pub fn max_dm_size(&self) -> u64 {
+        self.0.max_dm_size
+    }
+
+    /// Returns the maximum completion count of CQ moderation.
+    ///
+    /// Returns zero if the device does not support CQ moderation.
+    #[inline]
+    #[must_use]
+    pub fn max_cq_moderation_count(&self) -> u16 {
+        self.0.cq_mod_caps.max_cq_count
+    }
+
+    /// Returns the maximum period of CQ moderation in microseconds.
+    ///
+    /// Returns zero if the device does not support CQ moderation.
+    #[inline]
+    #[must_use]
+    pub fn max_cq_moderation_period(&self) -> u16 {
+        self.0.cq_mod_caps.max_cq_period
+    }
+
+    /// Returns the frequency of the HCA core clock in kHz.
+    ///
+    /// Completion timestamps are counted in ticks of this clock.
+    #[inline]
+    #[must_use]
+    pub fn core_clock_khz(&self) -> u64 {
+        self.0.hca_core_clock
+    }
+
+    /// Returns the mask of the valid bits of completion timestamps.
+    #[inline]
+    #[must_use]
+    pub fn completion_timestamp_mask(&self) -> u64 {
+        self.0.completion_timestamp_mask
+    }
+
+    /// Returns the maximum TSO payload size in bytes.
+    ///
+    /// Returns zero if the device does not support TSO.
+    #[inline]
+    #[must_use]
+    pub fn max_tso(&self) -> u32 {
+        self.0.tso_caps.max_tso
+    }
+
+    /// Returns the queue pair types which support TSO.
+    ///
+    /// Bit `1 << qp_type` is set for each supported type.
+    #[inline]
+    #[must_use]
+    pub fn tso_supported_qpts(&self) -> u32 {
+        self.0.tso_caps.supported_qpts
+    }
+
+    /// Returns the range of [`ModifyOptions::rate_limit`](crate::qp::ModifyOptions::rate_limit) in kbps.
+    ///
+    /// Returns `(0, 0)` if the device does not support packet pacing.
+    #[inline]
+    #[must_use]
+    pub fn qp_rate_limit_range(&self) -> (u32, u32) {
+        let caps = &self.0.packet_pacing_caps;
+        (caps.qp_rate_limit_min, caps.qp_rate_limit_max)
+    }
+
+    /// Returns the queue pair types which support packet pacing.
+    ///
+    /// Bit `1 << qp_type` is set for each supported type.
+    #[inline]
+    #[must_use]
+    pub fn packet_pacing_supported_qpts(&self) -> u32 {
+        self.0.packet_pacing_caps.supported_qpts
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn raw_packet_caps(&self) -> RawPacketCaps {
+        RawPacketCaps::from_bits_retain(self.0.raw_packet_caps)
+    }
+
+    /// Returns the maximum number of shared receive queues.
+    ///
+    /// Returns zero if the device does not support shared receive queues.
+    #[inline]
+    #[must_use]
+    pub fn max_srq(&self) -> u32 {
+        self.0.orig_attr.max_srq.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_srq_wr(&self) -> u32 {
+        self.0.orig_attr.max_srq_wr.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_srq_sge(&self) -> u32 {
+        self.0.orig_attr.max_srq_sge.numeric_cast()
+    }
+
+    /// Returns an overview of the features supported by the device
+    #[inline]
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        let attr = &*self.0;
+        let cap_flags = attr.orig_attr.device_cap_flags;
+        Capabilities {
+            supports_odp: attr.odp_caps.general_caps & u64::from(C::IBV_ODP_SUPPORT) != 0,
+            supports_atomics: attr.orig_attr.atomic_cap != C::IBV_ATOMIC_NONE,
+            supports_device_memory: attr.max_dm_size != 0,
+            supports_xrc: cap_flags & C::IBV_DEVICE_XRC != 0,
+            supports_flow_steering: cap_flags & C::IBV_DEVICE_MANAGED_FLOW_STEERING != 0,
+            supports_srq: attr.orig_attr.max_srq != 0,
+            supports_ud_ip_csum: cap_flags & C::IBV_DEVICE_UD_IP_CSUM != 0,
+            supports_raw_ip_csum: cap_flags & C::IBV_DEVICE_RAW_IP_CSUM != 0,
+        }
+    }
+}
+
+/// An overview of the features supported by a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Capabilities {
+    /// On-demand paging
+    pub supports_odp: bool,
+    /// Atomic operations
+    pub supports_atomics: bool,
+    /// Device memory allocation
+    pub supports_device_memory: bool,
+    /// Extended reliable connections
+    pub supports_xrc: bool,
+    /// Managed flow steering
+    pub supports_flow_steering: bool,
+    /// Shared receive queues
+    pub supports_srq: bool,
+    /// IP checksum offload of UD sends ([`SendFlags::IP_CSUM`](crate::wr::SendFlags::IP_CSUM))
+    pub supports_ud_ip_csum: bool,
+    /// IP checksum offload of raw packet sends ([`SendFlags::IP_CSUM`](crate::wr::SendFlags::IP_CSUM))
+    pub supports_raw_ip_csum: bool,
+}
+
+#[allow(clippy::same_name_method)]
+mod flags {
+    use super::*;
+
+    bitflags::bitflags! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct RawPacketCaps: u32 {
+            const CVLAN_STRIPPING = c_uint_to_u32(C::IBV_RAW_PACKET_CAP_CVLAN_STRIPPING);
+            const SCATTER_FCS = c_uint_to_u32(C::IBV_RAW_PACKET_CAP_SCATTER_FCS);
+            const IP_CSUM = c_uint_to_u32(C::IBV_RAW_PACKET_CAP_IP_CSUM);
+            const DELAY_DROP = c_uint_to_u32(C::IBV_RAW_PACKET_CAP_DELAY_DROP);
+        }
+    }
 }
+pub use self::flags::*;
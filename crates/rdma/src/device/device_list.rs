@@ -4,10 +4,12 @@ use crate::bindings as C;
 use crate::ctx::Context;
 use crate::error::last_error;
 
-use std::ffi::CStr;
+use std::ffi::{CStr, OsStr};
 use std::io;
 use std::ops::Deref;
 use std::os::raw::c_int;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
 use std::ptr::NonNull;
 use std::{fmt, mem, slice};
 
@@ -136,6 +138,17 @@ impl Device {
         }
     }
 
+    /// Returns the path of the uverbs character device, such as `/dev/infiniband/uverbs0`
+    #[inline]
+    #[must_use]
+    pub fn path(&self) -> PathBuf {
+        // SAFETY: reading a immutable field of a ffi type
+        let dev_name = unsafe { CStr::from_ptr((*self.ffi_ptr()).dev_name.as_ptr()) };
+        let mut path = PathBuf::from("/dev/infiniband");
+        path.push(OsStr::from_bytes(dev_name.to_bytes()));
+        path
+    }
+
     #[inline]
     pub fn open(&self) -> io::Result<Context> {
         Context::open(self)
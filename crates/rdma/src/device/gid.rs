@@ -1,3 +1,5 @@
+use super::PortAttr;
+
 use crate::bindings as C;
 use crate::ctx::Context;
 use crate::error::custom_error;
@@ -8,6 +10,8 @@ use std::net::Ipv6Addr;
 use std::os::raw::c_uint;
 use std::{fmt, io, slice};
 
+use numeric_cast::NumericCast;
+
 #[repr(transparent)]
 pub struct GidEntry(C::ibv_gid_entry);
 
@@ -28,6 +32,37 @@ impl GidEntry {
         }
     }
 
+    /// Returns the first entry of the GID table of the port whose type is `gid_type`
+    ///
+    /// This avoids hardcoding a GID index, which may change across reboots and kernel versions.
+    /// Use [`gid_index`](Self::gid_index) of the returned entry as the source GID index.
+    /// Returns `None` if no valid entry has the type.
+    #[inline]
+    pub fn query_first(
+        ctx: &Context,
+        port_num: u32,
+        gid_type: GidType,
+    ) -> io::Result<Option<Self>> {
+        let port_attr = PortAttr::query(ctx, port_num.numeric_cast())?;
+        for gid_index in 0..port_attr.gid_table_len() {
+            // empty entries of the table fail to be queried
+            let Ok(entry) = Self::query(ctx, port_num, gid_index) else {
+                continue;
+            };
+            if entry.gid_type() == gid_type {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the index of the entry in the GID table
+    #[inline]
+    #[must_use]
+    pub fn gid_index(&self) -> u32 {
+        self.0.gid_index
+    }
+
     #[inline]
     #[must_use]
     pub fn gid_type(&self) -> GidType {
@@ -97,6 +132,13 @@ impl Gid {
         // SAFETY: POD
         unsafe { self.0.global.interface_id }
     }
+
+    /// Returns true if the GID is a multicast GID, whose first byte is `0xff`
+    #[inline]
+    #[must_use]
+    pub const fn is_multicast(&self) -> bool {
+        self.as_bytes()[0] == 0xff
+    }
 }
 
 impl PartialEq for Gid {
@@ -202,6 +244,10 @@ mod tests {
         assert_eq!(debug, format!("Gid({GID_HEX})"));
         assert_eq!(lower_hex, GID_HEX);
         assert_eq!(upper_hex, GID_HEX.to_ascii_uppercase());
+        assert!(!gid.is_multicast());
+
+        let mgid = Gid::from_bytes(hex!("ff0e0000000000000000ffffe0000101"));
+        assert!(mgid.is_multicast());
     }
 
     #[test]
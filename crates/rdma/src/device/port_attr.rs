@@ -56,6 +56,39 @@ impl PortAttr {
     pub fn active_mtu(&self) -> Mtu {
         Mtu::from_c_uint(self.0.active_mtu)
     }
+
+    #[inline]
+    #[must_use]
+    pub fn max_mtu(&self) -> Mtu {
+        Mtu::from_c_uint(self.0.max_mtu)
+    }
+
+    /// Returns all MTUs supported by the port, in ascending order up to [`max_mtu`](Self::max_mtu)
+    #[inline]
+    pub fn supported_mtus(&self) -> impl Iterator<Item = Mtu> {
+        let max_mtu = self.max_mtu();
+        Mtu::ALL.into_iter().take_while(move |&mtu| mtu <= max_mtu)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn sm_lid(&self) -> u16 {
+        self.0.sm_lid
+    }
+
+    /// Returns the raw `active_speed` value defined by the IB spec
+    #[inline]
+    #[must_use]
+    pub fn active_speed(&self) -> u8 {
+        self.0.active_speed
+    }
+
+    /// Returns the raw `active_width` value defined by the IB spec
+    #[inline]
+    #[must_use]
+    pub fn active_width(&self) -> u8 {
+        self.0.active_width
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -102,7 +135,7 @@ impl LinkLayer {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u32)]
 pub enum Mtu {
     Mtu256 = c_uint_to_u32(C::IBV_MTU_256),
@@ -113,6 +146,15 @@ pub enum Mtu {
 }
 
 impl Mtu {
+    /// All MTUs in ascending order
+    pub const ALL: [Self; 5] = [
+        Self::Mtu256,
+        Self::Mtu512,
+        Self::Mtu1024,
+        Self::Mtu2048,
+        Self::Mtu4096,
+    ];
+
     #[allow(clippy::as_conversions, clippy::unnecessary_cast)]
     fn from_c_uint(val: c_uint) -> Self {
         assert!((1..6).contains(&val), "unexpected MTU value");
@@ -135,4 +177,83 @@ impl Mtu {
         let level = self.to_u32();
         1usize.wrapping_shl(level.wrapping_add(7))
     }
+
+    /// Returns the MTU whose size is exactly `bytes`.
+    #[inline]
+    #[must_use]
+    pub fn from_bytes(bytes: usize) -> Option<Self> {
+        match bytes {
+            256 => Some(Self::Mtu256),
+            512 => Some(Self::Mtu512),
+            1024 => Some(Self::Mtu1024),
+            2048 => Some(Self::Mtu2048),
+            4096 => Some(Self::Mtu4096),
+            _ => None,
+        }
+    }
+
+    /// Returns the largest MTU whose size does not exceed `bytes`.
+    ///
+    /// Returns `Mtu256` if `bytes` is less than 256.
+    #[inline]
+    #[must_use]
+    pub fn clamp_from_bytes(bytes: usize) -> Self {
+        match bytes {
+            0..=511 => Self::Mtu256,
+            512..=1023 => Self::Mtu512,
+            1024..=2047 => Self::Mtu1024,
+            2048..=4095 => Self::Mtu2048,
+            _ => Self::Mtu4096,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::Mtu;
+
+    use numeric_cast::NumericCast;
+    use serde::de::Error as _;
+    use serde::{Deserialize, Serialize};
+
+    impl Serialize for Mtu {
+        #[inline]
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            let bytes: u32 = self.size().numeric_cast();
+            <u32 as Serialize>::serialize(&bytes, serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Mtu {
+        #[inline]
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            let bytes = <u32 as Deserialize<'de>>::deserialize(deserializer)?;
+            Mtu::from_bytes(bytes.numeric_cast()).ok_or_else(|| D::Error::custom("invalid mtu"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mtu_bytes() {
+        assert!(Mtu::ALL.windows(2).all(|w| w[0] < w[1]));
+        for mtu in Mtu::ALL {
+            assert_eq!(Mtu::from_bytes(mtu.size()), Some(mtu));
+            assert_eq!(Mtu::clamp_from_bytes(mtu.size()), mtu);
+            assert_eq!(Mtu::clamp_from_bytes(mtu.size() + 1), mtu);
+        }
+        assert_eq!(Mtu::from_bytes(1000), None);
+        assert_eq!(Mtu::clamp_from_bytes(1000), Mtu::Mtu512);
+        assert_eq!(Mtu::clamp_from_bytes(100), Mtu::Mtu256);
+        assert_eq!(Mtu::clamp_from_bytes(9000), Mtu::Mtu4096);
+    }
 }
@@ -1,15 +1,21 @@
 use crate::bindings as C;
 use crate::ctx::Context;
-use crate::error::create_resource;
+use crate::error::{check_drop, create_resource, from_errno};
 
 use std::ptr::NonNull;
 use std::sync::Arc;
 use std::{io, mem};
 
+use numeric_cast::NumericCast;
+
 #[derive(Clone)]
 pub struct DeviceMemory(Arc<Owner>);
 
 impl DeviceMemory {
+    pub(crate) fn ffi_ptr(&self) -> *mut C::ibv_dm {
+        self.0.ffi_ptr()
+    }
+
     #[inline]
     #[must_use]
     pub fn options() -> DeviceMemoryOptions {
@@ -27,15 +33,68 @@ impl DeviceMemory {
             )?;
             Arc::new(Owner {
                 dm,
+                length: attr.length,
                 _ctx: ctx.clone(),
             })
         };
         Ok(Self(owner))
     }
+
+    /// Returns the length of the device memory
+    #[inline]
+    #[must_use]
+    pub fn length(&self) -> usize {
+        self.0.length
+    }
+
+    /// Copies `src` into the device memory at `offset`
+    ///
+    /// # Panics
+    /// + if the range is out of bounds
+    #[inline]
+    pub fn copy_from_slice(&self, offset: usize, src: &[u8]) -> io::Result<()> {
+        self.check_range(offset, src.len());
+        // SAFETY: ffi
+        let ret = unsafe {
+            let host_addr = src.as_ptr().cast();
+            C::ibv_memcpy_to_dm(self.ffi_ptr(), offset.numeric_cast(), host_addr, src.len())
+        };
+        if ret != 0 {
+            return Err(from_errno(ret.abs()));
+        }
+        Ok(())
+    }
+
+    /// Copies the device memory at `offset` into `dst`
+    ///
+    /// # Panics
+    /// + if the range is out of bounds
+    #[inline]
+    pub fn copy_to_slice(&self, offset: usize, dst: &mut [u8]) -> io::Result<()> {
+        self.check_range(offset, dst.len());
+        // SAFETY: ffi
+        let ret = unsafe {
+            let host_addr = dst.as_mut_ptr().cast();
+            C::ibv_memcpy_from_dm(host_addr, self.ffi_ptr(), offset.numeric_cast(), dst.len())
+        };
+        if ret != 0 {
+            return Err(from_errno(ret.abs()));
+        }
+        Ok(())
+    }
+
+    fn check_range(&self, offset: usize, len: usize) {
+        let end = offset.checked_add(len);
+        assert!(
+            end.is_some_and(|end| end <= self.length()),
+            "device memory range out of bounds"
+        );
+    }
 }
 
 struct Owner {
     dm: NonNull<C::ibv_dm>,
+    length: usize,
     _ctx: Context,
 }
 
@@ -56,7 +115,7 @@ impl Drop for Owner {
         unsafe {
             let dm = self.ffi_ptr();
             let ret = C::ibv_free_dm(dm);
-            assert_eq!(ret, 0);
+            check_drop(ret, "ibv_free_dm");
         }
     }
 }
@@ -74,3 +133,19 @@ impl Default for DeviceMemoryOptions {
         }
     }
 }
+
+impl DeviceMemoryOptions {
+    /// Sets the length of the device memory in bytes
+    #[inline]
+    pub fn length(&mut self, length: usize) -> &mut Self {
+        self.attr.length = length;
+        self
+    }
+
+    /// Sets the required alignment of the device memory as a power of two
+    #[inline]
+    pub fn log_align_req(&mut self, log_align_req: u32) -> &mut Self {
+        self.attr.log_align_req = log_align_req;
+        self
+    }
+}
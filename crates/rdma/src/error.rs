@@ -1,5 +1,96 @@
-use std::io;
 use std::ptr::NonNull;
+use std::{fmt, io};
+
+/// An error which occurs when creating a resource
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RdmaError {
+    /// The verb is not implemented by the driver (`EOPNOTSUPP` or `ENOSYS`)
+    Unsupported(i32),
+    /// The device or the system is out of resources (`ENOMEM`)
+    OutOfResources(i32),
+    /// An argument is invalid (`EINVAL`)
+    InvalidArgument(i32),
+    /// Other errors
+    Io(io::Error),
+}
+
+impl RdmaError {
+    /// Classifies an errno
+    #[inline]
+    #[must_use]
+    pub fn from_errno(errno: i32) -> Self {
+        match errno {
+            libc::EOPNOTSUPP | libc::ENOSYS => Self::Unsupported(errno),
+            libc::ENOMEM => Self::OutOfResources(errno),
+            libc::EINVAL => Self::InvalidArgument(errno),
+            _ => Self::Io(from_errno(errno)),
+        }
+    }
+
+    /// Returns the errno of the error, if any
+    #[inline]
+    #[must_use]
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match *self {
+            Self::Unsupported(errno)
+            | Self::OutOfResources(errno)
+            | Self::InvalidArgument(errno) => Some(errno),
+            Self::Io(ref e) => e.raw_os_error(),
+        }
+    }
+}
+
+impl fmt::Display for RdmaError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(_) => f.write_str("operation not supported by the driver"),
+            Self::OutOfResources(_) => f.write_str("out of resources"),
+            Self::InvalidArgument(_) => f.write_str("invalid argument"),
+            Self::Io(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl std::error::Error for RdmaError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RdmaError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        if let Some(errno) = e.raw_os_error() {
+            return Self::from_errno(errno);
+        }
+        let kind = e.kind();
+        match e.into_inner() {
+            Some(inner) => match inner.downcast::<RdmaError>() {
+                Ok(inner) => *inner,
+                Err(inner) => Self::Io(io::Error::new(kind, inner)),
+            },
+            None => Self::Io(kind.into()),
+        }
+    }
+}
+
+impl From<RdmaError> for io::Error {
+    #[inline]
+    fn from(e: RdmaError) -> Self {
+        match e {
+            RdmaError::Unsupported(errno)
+            | RdmaError::OutOfResources(errno)
+            | RdmaError::InvalidArgument(errno) => from_errno(errno),
+            RdmaError::Io(e) => e,
+        }
+    }
+}
 
 pub fn last_error() -> io::Error {
     io::Error::last_os_error()
@@ -19,7 +110,7 @@ pub fn from_errno(errno: i32) -> io::Error {
 pub fn create_resource<T, E>(
     f: impl FnOnce() -> *mut T,
     e: impl FnOnce() -> E,
-) -> io::Result<NonNull<T>>
+) -> Result<NonNull<T>, RdmaError>
 where
     E: Into<Box<dyn std::error::Error + Send + Sync>>,
 {
@@ -28,12 +119,44 @@ where
     NonNull::new(p).ok_or_else(|| {
         let errno = get_errno();
         if errno != 0 {
-            return from_errno(errno);
+            return RdmaError::from_errno(errno);
         }
-        custom_error(e())
+        RdmaError::Io(custom_error(e()))
     })
 }
 
+/// Reports a failure of releasing a resource in `Drop`
+///
+/// Panicking in `Drop` may abort the process, so the error is logged instead.
+/// The error is usually caused by a wrong teardown order,
+/// e.g. destroying a completion queue which is still used by a queue pair.
+#[cold]
+#[inline(never)]
+fn report_drop_error(verb: &str, errno: i32) {
+    let err = from_errno(errno);
+    #[cfg(feature = "tracing")]
+    tracing::error!(verb, %err, "failed to release rdma resource");
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("rdma: {verb} failed: {err}");
+}
+
+/// Reports a resource which is leaked in `Drop` because releasing it would fail
+#[cold]
+#[inline(never)]
+pub fn report_leak(resource: &str, reason: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::error!(resource, reason, "leaking rdma resource");
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("rdma: leaking {resource}: {reason}");
+}
+
+pub fn check_drop(ret: i32, verb: &str) {
+    if ret != 0 {
+        let errno = if ret < 0 { get_errno() } else { ret };
+        report_drop_error(verb, errno);
+    }
+}
+
 pub fn set_errno(errno: i32) {
     // SAFETY: write tls value
     unsafe { libc::__errno_location().write(errno) };
@@ -43,3 +166,45 @@ pub fn get_errno() -> i32 {
     // SAFETY: read tls value
     unsafe { libc::__errno_location().read() }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_errno() {
+        assert!(matches!(
+            RdmaError::from_errno(libc::EOPNOTSUPP),
+            RdmaError::Unsupported(libc::EOPNOTSUPP)
+        ));
+        assert!(matches!(
+            RdmaError::from_errno(libc::ENOSYS),
+            RdmaError::Unsupported(libc::ENOSYS)
+        ));
+        assert!(matches!(
+            RdmaError::from_errno(libc::ENOMEM),
+            RdmaError::OutOfResources(libc::ENOMEM)
+        ));
+        assert!(matches!(
+            RdmaError::from_errno(libc::EINVAL),
+            RdmaError::InvalidArgument(libc::EINVAL)
+        ));
+        assert!(matches!(RdmaError::from_errno(libc::EIO), RdmaError::Io(_)));
+    }
+
+    #[test]
+    fn errno_round_trip() {
+        let e: io::Error = RdmaError::from_errno(libc::EOPNOTSUPP).into();
+        assert_eq!(e.raw_os_error(), Some(libc::EOPNOTSUPP));
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+
+        let e = RdmaError::from(e);
+        assert!(matches!(e, RdmaError::Unsupported(libc::EOPNOTSUPP)));
+        assert_eq!(e.raw_os_error(), Some(libc::EOPNOTSUPP));
+
+        let e: io::Error = RdmaError::from_errno(libc::ENOMEM).into();
+        assert_eq!(e.raw_os_error(), Some(libc::ENOMEM));
+        let e: io::Error = RdmaError::from_errno(libc::EIO).into();
+        assert_eq!(e.raw_os_error(), Some(libc::EIO));
+    }
+}
@@ -0,0 +1,238 @@
+//! Asynchronous events of a device
+
+use crate::bindings as C;
+use crate::cq::CompletionQueue;
+use crate::ctx::Context;
+use crate::error::last_error;
+use crate::qp::QueuePair;
+
+use std::os::raw::c_uint;
+use std::{io, mem, ptr};
+
+use numeric_cast::NumericCast;
+
+/// An asynchronous event which is reported by a device
+///
+/// The event is acknowledged when it is dropped.
+/// Destroying a resource affiliated with the event waits for the acknowledgement,
+/// so the event should not be held for a long time.
+pub struct AsyncEvent {
+    event: C::ibv_async_event,
+    _ctx: Context,
+}
+
+/// SAFETY: owned type
+unsafe impl Send for AsyncEvent {}
+/// SAFETY: owned type
+unsafe impl Sync for AsyncEvent {}
+
+impl AsyncEvent {
+    /// Waits for the next asynchronous event of the context
+    ///
+    /// This method blocks until an event arrives.
+    #[inline]
+    pub fn wait(ctx: &Context) -> io::Result<Self> {
+        // SAFETY: ffi
+        unsafe {
+            let mut event: C::ibv_async_event = mem::zeroed();
+            let ret = C::ibv_get_async_event(ctx.ffi_ptr(), ptr::addr_of_mut!(event));
+            if ret != 0 {
+                return Err(last_error());
+            }
+            Ok(Self {
+                event,
+                _ctx: ctx.clone(),
+            })
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn event_type(&self) -> AsyncEventType {
+        AsyncEventType::from_c_uint(self.event.event_type)
+    }
+
+    /// Returns the port event if this is an event of a port
+    ///
+    /// The cached [`PortAttr`](crate::device::PortAttr) of the port should be queried again.
+    #[inline]
+    #[must_use]
+    pub fn port_event(&self) -> Option<PortEvent> {
+        let port_num = || {
+            // SAFETY: the element of port events is the port number
+            let port_num = unsafe { self.event.element.port_num };
+            port_num.numeric_cast()
+        };
+        let event = match self.event_type() {
+            AsyncEventType::PortActive => PortEvent::Active(port_num()),
+            AsyncEventType::PortError => PortEvent::Error(port_num()),
+            AsyncEventType::LidChange => PortEvent::LidChange(port_num()),
+            AsyncEventType::PkeyChange => PortEvent::PkeyChange(port_num()),
+            AsyncEventType::SmChange => PortEvent::SmChange(port_num()),
+            AsyncEventType::ClientReregister => PortEvent::ClientReregister(port_num()),
+            AsyncEventType::GidChange => PortEvent::GidChange(port_num()),
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    /// Returns true if this is a [`CqError`](AsyncEventType::CqError) event of `cq`
+    ///
+    /// A completion queue reports this event when it overruns,
+    /// after which it stops reporting completions.
+    /// See [`CompletionQueueOptions::cqe`](crate::cq::CompletionQueueOptions::cqe).
+    #[inline]
+    #[must_use]
+    pub fn is_cq_error_of(&self, cq: &CompletionQueue) -> bool {
+        if self.event_type() != AsyncEventType::CqError {
+            return false;
+        }
+        // SAFETY: the element of cq events is the cq
+        let event_cq = unsafe { self.event.element.cq };
+        event_cq == cq.as_raw()
+    }
+
+    /// Returns true if this is a [`SqDrained`](AsyncEventType::SqDrained) event of `qp`
+    ///
+    /// The event is reported once after the queue pair is moved to SQD
+    /// with [`ModifyOptions::en_sqd_async_notify`](crate::qp::ModifyOptions::en_sqd_async_notify)
+    /// and all outstanding send requests are complete.
+    /// The attributes of the queue pair can then be modified in SQD,
+    /// before it is moved back to RTS.
+    ///
+    /// ```no_run
+    /// use rdma::ctx::Context;
+    /// use rdma::event::AsyncEvent;
+    /// use rdma::qp::{ModifyOptions, QueuePair, QueuePairState};
+    ///
+    /// fn quiesce(ctx: &Context, qp: &QueuePair) -> std::io::Result<()> {
+    ///     let mut options = ModifyOptions::default();
+    ///     options
+    ///         .qp_state(QueuePairState::SendQueueDrained)
+    ///         .en_sqd_async_notify(true);
+    ///     qp.modify(options)?;
+    ///
+    ///     loop {
+    ///         let event = AsyncEvent::wait(ctx)?;
+    ///         if event.is_sq_drained_of(qp) {
+    ///             break;
+    ///         }
+    ///         // handle the other events here
+    ///     }
+    ///
+    ///     // modify the attributes which are allowed in SQD here
+    ///
+    ///     let mut options = ModifyOptions::default();
+    ///     options.qp_state(QueuePairState::ReadyToSend);
+    ///     qp.modify(options)
+    /// }
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn is_sq_drained_of(&self, qp: &QueuePair) -> bool {
+        if self.event_type() != AsyncEventType::SqDrained {
+            return false;
+        }
+        // SAFETY: the element of qp events is the qp
+        let event_qp = unsafe { self.event.element.qp };
+        event_qp == qp.as_raw()
+    }
+}
+
+impl Drop for AsyncEvent {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: ffi
+        unsafe { C::ibv_ack_async_event(ptr::addr_of_mut!(self.event)) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AsyncEventType {
+    CqError,
+    QpFatal,
+    QpRequestError,
+    QpAccessError,
+    CommunicationEstablished,
+    SqDrained,
+    PathMigrated,
+    PathMigrationError,
+    DeviceFatal,
+    PortActive,
+    PortError,
+    LidChange,
+    PkeyChange,
+    SmChange,
+    SrqError,
+    SrqLimitReached,
+    QpLastWqeReached,
+    ClientReregister,
+    GidChange,
+    WqFatal,
+    /// An event which is unknown to this crate
+    Unknown,
+}
+
+impl AsyncEventType {
+    fn from_c_uint(val: c_uint) -> Self {
+        match val {
+            C::IBV_EVENT_CQ_ERR => Self::CqError,
+            C::IBV_EVENT_QP_FATAL => Self::QpFatal,
+            C::IBV_EVENT_QP_REQ_ERR => Self::QpRequestError,
+            C::IBV_EVENT_QP_ACCESS_ERR => Self::QpAccessError,
+            C::IBV_EVENT_COMM_EST => Self::CommunicationEstablished,
+            C::IBV_EVENT_SQ_DRAINED => Self::SqDrained,
+            C::IBV_EVENT_PATH_MIG => Self::PathMigrated,
+            C::IBV_EVENT_PATH_MIG_ERR => Self::PathMigrationError,
+            C::IBV_EVENT_DEVICE_FATAL => Self::DeviceFatal,
+            C::IBV_EVENT_PORT_ACTIVE => Self::PortActive,
+            C::IBV_EVENT_PORT_ERR => Self::PortError,
+            C::IBV_EVENT_LID_CHANGE => Self::LidChange,
+            C::IBV_EVENT_PKEY_CHANGE => Self::PkeyChange,
+            C::IBV_EVENT_SM_CHANGE => Self::SmChange,
+            C::IBV_EVENT_SRQ_ERR => Self::SrqError,
+            C::IBV_EVENT_SRQ_LIMIT_REACHED => Self::SrqLimitReached,
+            C::IBV_EVENT_QP_LAST_WQE_REACHED => Self::QpLastWqeReached,
+            C::IBV_EVENT_CLIENT_REREGISTER => Self::ClientReregister,
+            C::IBV_EVENT_GID_CHANGE => Self::GidChange,
+            C::IBV_EVENT_WQ_FATAL => Self::WqFatal,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// An event of a port, which carries the port number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortEvent {
+    /// The link becomes active
+    Active(u8),
+    /// The link becomes inactive
+    Error(u8),
+    /// The LID of the port is changed
+    LidChange(u8),
+    /// The `P_Key` table of the port is changed
+    PkeyChange(u8),
+    /// The SM of the port is changed
+    SmChange(u8),
+    /// The SM requests the client to reregister
+    ClientReregister(u8),
+    /// The GID table of the port is changed
+    GidChange(u8),
+}
+
+impl PortEvent {
+    #[inline]
+    #[must_use]
+    pub fn port_num(self) -> u8 {
+        match self {
+            Self::Active(n)
+            | Self::Error(n)
+            | Self::LidChange(n)
+            | Self::PkeyChange(n)
+            | Self::SmChange(n)
+            | Self::ClientReregister(n)
+            | Self::GidChange(n) => n,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Global route header of UD receives
+
+use crate::device::Gid;
+
+/// The size of the space reserved for the GRH at the beginning of a UD receive buffer
+pub const GRH_SIZE: usize = 40;
+
+/// A global route header at the beginning of a received UD buffer
+///
+/// The header is only valid if the work completion has `IBV_WC_GRH` in its flags.
+/// On RoCE v2 with IPv4 addresses, the last 20 bytes hold an IPv4 header instead.
+#[derive(Clone, Copy)]
+pub struct GrhHeader<'a>(&'a [u8; GRH_SIZE]);
+
+impl<'a> GrhHeader<'a> {
+    /// Borrows the header from the first 40 bytes of `buf`
+    ///
+    /// Returns `None` if `buf` is shorter than 40 bytes.
+    #[inline]
+    #[must_use]
+    pub fn parse(buf: &'a [u8]) -> Option<Self> {
+        let bytes = buf.get(..GRH_SIZE)?;
+        bytes.try_into().ok().map(Self)
+    }
+
+    fn version_class_flow(self) -> u32 {
+        let [a, b, c, d, ..] = *self.0;
+        u32::from_be_bytes([a, b, c, d])
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn traffic_class(self) -> u8 {
+        let [.., traffic_class] = (self.version_class_flow() >> 20).to_be_bytes();
+        traffic_class
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn flow_label(self) -> u32 {
+        self.version_class_flow() & 0x000f_ffff
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn hop_limit(self) -> u8 {
+        self.0[7]
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn source_gid(self) -> Gid {
+        Gid::from_bytes(gid_bytes(self.0, 8))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn dest_gid(self) -> Gid {
+        Gid::from_bytes(gid_bytes(self.0, 24))
+    }
+}
+
+fn gid_bytes(header: &[u8; GRH_SIZE], offset: usize) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&header[offset..offset + 16]);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_grh() {
+        assert!(GrhHeader::parse(&[0; 39]).is_none());
+
+        let mut buf = [0u8; 64];
+        buf[..4].copy_from_slice(&0x6ab1_2345_u32.to_be_bytes());
+        buf[7] = 64;
+        buf[8..24].copy_from_slice(&[1; 16]);
+        buf[24..40].copy_from_slice(&[2; 16]);
+
+        let grh = GrhHeader::parse(&buf).unwrap();
+        assert_eq!(grh.traffic_class(), 0xab);
+        assert_eq!(grh.flow_label(), 0x1_2345);
+        assert_eq!(grh.hop_limit(), 64);
+        assert_eq!(grh.source_gid().as_bytes(), &[1; 16]);
+        assert_eq!(grh.dest_gid().as_bytes(), &[2; 16]);
+    }
+}
@@ -1,3 +1,23 @@
+//! Low-level RDMA API
+//!
+//! # Dependence on `std`
+//!
+//! The crate requires `std`. It does not open sockets or spawn threads;
+//! connection establishment over TCP and async runtimes live in the examples.
+//! The parts of `std` which are used are:
+//!
+//! + `std::io`: all fallible functions return [`std::io::Result`]
+//! + `std::sync`: resources are reference counted by `Arc`,
+//!   and `parking_lot` locks protect the internal shared state ([`cc`], [`qp`])
+//! + `std::os::unix`: file descriptors of completion channels ([`cc`])
+//! + `std::time`: timeouts of [`cc::CompChannelSet`] and the wall clock of [`ctx::RtValues`]
+//! + `std::net`: conversions between [`device::Gid`] and `Ipv6Addr`
+//! + `std::env`: device selection of `test_util` (feature `test-util`)
+//!
+//! The resource types (protection domains, memory regions, queue pairs and completion queues)
+//! do not depend on networking. They use `alloc`, `libc`, the `std::io` error type,
+//! and the locks of `parking_lot` in [`qp`].
+
 #![deny(clippy::all, clippy::pedantic, clippy::cargo)]
 #![allow(
     clippy::module_name_repetitions,
@@ -50,11 +70,21 @@ pub mod bindings {
 
     mod ibverbs;
     pub use self::ibverbs::*;
+
+    #[cfg(feature = "mlx5")]
+    mod mlx5dv;
+    #[cfg(feature = "mlx5")]
+    pub use self::mlx5dv::*;
 }
 
 mod error;
+pub use self::error::RdmaError;
+
 mod weakset;
 
+mod psn;
+pub use self::psn::random_psn;
+
 pub mod device {
     mod device_list;
     pub use self::device_list::*;
@@ -74,13 +104,27 @@ pub mod device {
 
 pub mod ah;
 pub mod cc;
+pub mod check;
+pub mod connect;
 pub mod cq;
 pub mod ctx;
 pub mod dm;
+pub mod event;
+pub mod grh;
 pub mod mr;
 pub mod mw;
 pub mod pd;
 pub mod qp;
+pub mod qp_ex;
+pub mod recv;
 pub mod srq;
+pub mod td;
+pub mod ud;
 pub mod wc;
 pub mod wr;
+
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+#[cfg(feature = "mlx5")]
+pub mod mlx5;
@@ -0,0 +1,139 @@
+//! The DC (Dynamically Connected) transport of mlx5 devices (feature `mlx5`)
+//!
+//! A DC target ([`DcTarget`]) receives from any DC initiator which knows its
+//! DCT number and access key. A DC initiator ([`DcInitiator`]) sends to a different
+//! target with each work request, so one initiator replaces many RC connections.
+//!
+//! Both are queue pairs of type [`QueuePairType::Driver`] and are moved through the states
+//! with [`QueuePair::modify`]:
+//!
+//! + a DC target goes to INIT (`pkey_index`, `port_num`, `qp_access_flags`)
+//!   and then to RTR (`path_mtu`, `min_rnr_timer`, `ah_attr`)
+//! + a DC initiator goes to INIT (`pkey_index`, `port_num`), to RTR (`path_mtu`)
+//!   and then to RTS (`timeout`, `retry_cnt`, `rnr_retry`, `sq_psn`, `max_rd_atomic`)
+//!
+//! An initiator posts with [`QueuePair::start_wr`] and sets the target of each request
+//! with [`WrBatch::dc_addr`](crate::qp_ex::WrBatch::dc_addr).
+
+use crate::bindings as C;
+use crate::cq::CompletionQueue;
+use crate::ctx::Context;
+use crate::qp::{QueuePair, QueuePairOptions, QueuePairType};
+use crate::srq::SharedReceiveQueue;
+
+use std::io;
+use std::ptr;
+
+/// A DC target
+///
+/// Incoming requests are received into the shared receive queue.
+pub struct DcTarget {
+    qp: QueuePair,
+    access_key: u64,
+}
+
+impl DcTarget {
+    /// Creates a DC target
+    ///
+    /// `options` must set the protection domain.
+    /// The receive side of `options` is replaced by `srq` and `recv_cq`.
+    /// Initiators must present `access_key` to send to the target.
+    #[inline]
+    pub fn create(
+        ctx: &Context,
+        mut options: QueuePairOptions,
+        srq: &SharedReceiveQueue,
+        recv_cq: &CompletionQueue,
+        access_key: u64,
+    ) -> io::Result<Self> {
+        options
+            .qp_type(QueuePairType::Driver)
+            .srq_with_cq(srq, recv_cq);
+
+        let mut dv_attr = dc_init_attr(C::MLX5DV_DCTYPE_DCT);
+        dv_attr.dc_init_attr.u.dct_access_key = access_key;
+
+        // SAFETY: ffi
+        let qp = unsafe {
+            QueuePair::create_with(ctx, options, |context, qp_attr| {
+                C::mlx5dv_create_qp(context, qp_attr, ptr::addr_of_mut!(dv_attr))
+            })?
+        };
+        Ok(Self { qp, access_key })
+    }
+
+    /// Returns the underlying queue pair
+    #[inline]
+    #[must_use]
+    pub fn queue_pair(&self) -> &QueuePair {
+        &self.qp
+    }
+
+    /// Returns the DCT number, which initiators use as the remote DCT number
+    #[inline]
+    #[must_use]
+    pub fn dct_num(&self) -> u32 {
+        self.qp.qp_num()
+    }
+
+    /// Returns the access key of the target
+    #[inline]
+    #[must_use]
+    pub fn access_key(&self) -> u64 {
+        self.access_key
+    }
+}
+
+/// A DC initiator
+pub struct DcInitiator {
+    qp: QueuePair,
+}
+
+impl DcInitiator {
+    /// Creates a DC initiator
+    ///
+    /// `options` must set [`QueuePairOptions::send_ops_flags`],
+    /// since requests are posted with the extended work request API.
+    ///
+    /// # Errors
+    /// + `InvalidInput` if `options` has no send operations
+    #[inline]
+    pub fn create(ctx: &Context, mut options: QueuePairOptions) -> io::Result<Self> {
+        if options.get_send_ops().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a DC initiator requires send_ops_flags",
+            ));
+        }
+        options.qp_type(QueuePairType::Driver);
+
+        let mut dv_attr = dc_init_attr(C::MLX5DV_DCTYPE_DCI);
+
+        // SAFETY: ffi
+        let qp = unsafe {
+            QueuePair::create_with(ctx, options, |context, qp_attr| {
+                C::mlx5dv_create_qp(context, qp_attr, ptr::addr_of_mut!(dv_attr))
+            })?
+        };
+        Ok(Self { qp })
+    }
+
+    /// Returns the underlying queue pair
+    #[inline]
+    #[must_use]
+    pub fn queue_pair(&self) -> &QueuePair {
+        &self.qp
+    }
+}
+
+fn dc_init_attr(dc_type: C::mlx5dv_dc_type) -> C::mlx5dv_qp_init_attr {
+    C::mlx5dv_qp_init_attr {
+        comp_mask: C::MLX5DV_QP_INIT_ATTR_MASK_DC,
+        create_flags: 0,
+        dc_init_attr: C::mlx5dv_dc_init_attr {
+            dc_type,
+            u: C::mlx5dv_dc_init_attr_union { dct_access_key: 0 },
+        },
+        send_ops_flags: 0,
+    }
+}
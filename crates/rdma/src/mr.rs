@@ -1,11 +1,15 @@
 use crate::bindings as C;
-use crate::error::create_resource;
+use crate::dm::DeviceMemory;
+use crate::error::{check_drop, create_resource};
 use crate::pd::ProtectionDomain;
 use crate::utils::ptr_to_addr;
+use crate::wr::Sge;
 
+use std::fs::File;
 use std::io;
 use std::os::raw::c_void;
-use std::ptr::NonNull;
+use std::os::unix::io::AsRawFd;
+use std::ptr::{self, NonNull};
 use std::sync::Arc;
 
 use numeric_cast::NumericCast;
@@ -18,6 +22,16 @@ impl<T> MemoryRegion<T> {
         self.0.ffi_ptr()
     }
 
+    /// Returns the underlying `ibv_mr` pointer
+    ///
+    /// The pointer is valid as long as the memory region is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_mr {
+        self.ffi_ptr()
+    }
+
     /// Registers a memory region associated with the protection domain `pd`.
     /// The memory region's starting address is `addr` and its size is `length`.
     ///
@@ -35,15 +49,117 @@ impl<T> MemoryRegion<T> {
     ) -> io::Result<Self> {
         let owner = {
             let addr: *mut c_void = addr.cast();
-            let access_flags = access_flags.to_c_uint();
+            let access = access_flags.to_c_uint();
+            let mr = create_resource(
+                || C::ibv_reg_mr(pd.ffi_ptr(), addr, length, access),
+                || "failed to register memory region",
+            )?;
+            Arc::new(Owner {
+                mr,
+                access_flags,
+                iova: ptr_to_addr(addr).numeric_cast(),
+                metadata,
+                _pd: pd.clone(),
+                _dm: None,
+            })
+        };
+        Ok(Self(owner))
+    }
+
+    /// Registers a memory region which is addressed by `iova` instead of `addr`
+    ///
+    /// The work requests access the memory region in the iova address space:
+    /// the byte at `addr + n` is addressed by `iova + n`, both in local sges
+    /// and by the remote peer with the rkey. This hides the local layout from the peer.
+    /// [`sge`](Self::sge), [`sge_range`](Self::sge_range) and [`chunk`](Self::chunk) use the iova.
+    ///
+    /// # Safety
+    /// 1. the memory region must be valid until it is deregistered
+    /// 2. the memory region must be initialized before it is read for the first time
+    #[allow(clippy::arc_with_non_send_sync)] // FIXME: false positive
+    #[inline]
+    pub unsafe fn register_iova(
+        pd: &ProtectionDomain,
+        addr: *mut u8,
+        length: usize,
+        iova: u64,
+        access_flags: AccessFlags,
+        metadata: T,
+    ) -> io::Result<Self> {
+        let owner = {
+            let addr: *mut c_void = addr.cast();
+            let access = access_flags.to_c_uint();
             let mr = create_resource(
-                || C::ibv_reg_mr(pd.ffi_ptr(), addr, length, access_flags),
+                || C::ibv_reg_mr_iova2(pd.ffi_ptr(), addr, length, iova, access),
                 || "failed to register memory region",
             )?;
             Arc::new(Owner {
                 mr,
+                access_flags,
+                iova,
                 metadata,
                 _pd: pd.clone(),
+                _dm: None,
+            })
+        };
+        Ok(Self(owner))
+    }
+
+    /// Registers a memory region which is addressed from zero
+    ///
+    /// The byte at `addr + n` is addressed by `n` in work requests, so the remote peer
+    /// only needs the rkey and the length, regardless of the local virtual address.
+    ///
+    /// This is [`register_iova`](Self::register_iova) with an iova of zero.
+    /// Note that [`AccessFlags::ZERO_BASED`] is not needed here. The flag applies to
+    /// the binding of memory windows and to [`register_dm`](Self::register_dm).
+    ///
+    /// # Safety
+    /// 1. the memory region must be valid until it is deregistered
+    /// 2. the memory region must be initialized before it is read for the first time
+    #[inline]
+    pub unsafe fn register_zero_based(
+        pd: &ProtectionDomain,
+        addr: *mut u8,
+        length: usize,
+        access_flags: AccessFlags,
+        metadata: T,
+    ) -> io::Result<Self> {
+        Self::register_iova(pd, addr, length, 0, access_flags, metadata)
+    }
+
+    /// Registers a memory region over the device memory `dm`
+    ///
+    /// The memory region covers `length` bytes starting at `offset` of the device memory.
+    /// It is always zero-based: the first byte is addressed by zero in work requests,
+    /// so [`AccessFlags::ZERO_BASED`] is added to `access_flags`.
+    /// The content is accessed by the host with [`DeviceMemory::copy_to_slice`]
+    /// and [`DeviceMemory::copy_from_slice`].
+    #[inline]
+    pub fn register_dm(
+        pd: &ProtectionDomain,
+        dm: &DeviceMemory,
+        offset: usize,
+        length: usize,
+        access_flags: AccessFlags,
+        metadata: T,
+    ) -> io::Result<Self> {
+        let access_flags = access_flags | AccessFlags::ZERO_BASED;
+        // SAFETY: ffi
+        let owner = unsafe {
+            let access = access_flags.to_c_uint();
+            let dm_offset = offset.numeric_cast();
+            let mr = create_resource(
+                || C::ibv_reg_dm_mr(pd.ffi_ptr(), dm.ffi_ptr(), dm_offset, length, access),
+                || "failed to register device memory region",
+            )?;
+            Arc::new(Owner {
+                mr,
+                access_flags,
+                iova: 0,
+                metadata,
+                _pd: pd.clone(),
+                _dm: Some(dm.clone()),
             })
         };
         Ok(Self(owner))
@@ -81,6 +197,16 @@ impl<T> MemoryRegion<T> {
         unsafe { ptr_to_addr((*mr).addr) }.numeric_cast()
     }
 
+    /// Returns the address of the memory region in work requests
+    ///
+    /// This is the same as [`addr_u64`](Self::addr_u64) unless the memory region
+    /// is registered by [`register_iova`](Self::register_iova).
+    #[inline]
+    #[must_use]
+    pub fn iova(&self) -> u64 {
+        self.0.iova
+    }
+
     #[inline]
     #[must_use]
     pub fn length(&self) -> usize {
@@ -89,19 +215,252 @@ impl<T> MemoryRegion<T> {
         unsafe { (*mr).length }
     }
 
+    /// Returns the access flags which the memory region is registered with
+    #[inline]
+    #[must_use]
+    pub fn access_flags(&self) -> AccessFlags {
+        self.0.access_flags
+    }
+
     #[inline]
     #[must_use]
     pub fn metadata(&self) -> &T {
         self.0.metadata()
     }
+
+    /// Returns the sge covering the whole memory region
+    ///
+    /// # Panics
+    /// + if the length of the memory region does not fit in `u32`
+    #[inline]
+    #[must_use]
+    pub fn sge(&self) -> Sge {
+        self.sge_range(0, self.length())
+    }
+
+    /// Returns the sge covering `len` bytes starting at `offset` of the memory region
+    ///
+    /// # Panics
+    /// + if the range is out of bounds
+    /// + if `len` does not fit in `u32`
+    #[inline]
+    #[must_use]
+    pub fn sge_range(&self, offset: usize, len: usize) -> Sge {
+        let end = offset.checked_add(len);
+        assert!(
+            end.is_some_and(|end| end <= self.length()),
+            "sge range out of bounds"
+        );
+        assert!(u32::try_from(len).is_ok(), "sge length does not fit in u32");
+        self.sge_at(offset, len)
+    }
+
+    /// Returns the sge of the `index`-th chunk of the memory region
+    ///
+    /// The last chunk may be shorter than `chunk_size`.
+    ///
+    /// # Panics
+    /// + if `chunk_size` is zero or does not fit in `u32`
+    /// + if `index` is out of bounds
+    #[inline]
+    #[must_use]
+    pub fn chunk(&self, index: usize, chunk_size: usize) -> Sge {
+        let (offset, len) =
+            chunk_range(self.length(), index, chunk_size).expect("chunk index out of bounds");
+        self.sge_at(offset, len)
+    }
+
+    /// Splits the memory region into chunks of `chunk_size` bytes and returns their sges
+    ///
+    /// The last chunk may be shorter than `chunk_size`.
+    ///
+    /// # Panics
+    /// + if `chunk_size` is zero or does not fit in `u32`
+    #[inline]
+    pub fn chunks(&self, chunk_size: usize) -> impl Iterator<Item = Sge> + '_ {
+        // panic here instead of on the first `next`
+        check_chunk_size(chunk_size);
+        let length = self.length();
+        (0..).map_while(move |index| {
+            let (offset, len) = chunk_range(length, index, chunk_size)?;
+            Some(self.sge_at(offset, len))
+        })
+    }
+
+    fn sge_at(&self, offset: usize, len: usize) -> Sge {
+        let offset: u64 = offset.numeric_cast();
+        Sge {
+            addr: self.iova().wrapping_add(offset),
+            length: len.numeric_cast(),
+            lkey: self.lkey(),
+        }
+    }
+}
+
+/// A shared read/write mapping of a file
+///
+/// The mapping is unmapped when it is dropped.
+/// As the metadata of a [`FileMappedRegion`], it is dropped after the memory region is deregistered.
+pub struct FileMapping {
+    addr: NonNull<u8>,
+    length: usize,
+}
+
+/// SAFETY: owned type
+unsafe impl Send for FileMapping {}
+/// SAFETY: owned type
+unsafe impl Sync for FileMapping {}
+
+impl FileMapping {
+    /// Maps `length` bytes of `file` starting at `offset`
+    ///
+    /// `file` must be opened for reading and writing.
+    /// The file can be closed after the mapping is created.
+    ///
+    /// # Errors
+    /// + `InvalidInput` if `length` is zero, `offset` is not aligned to the page size,
+    ///   or the file is smaller than `offset + length`
+    #[inline]
+    pub fn map(file: &File, offset: u64, length: usize) -> io::Result<Self> {
+        let file_len = file.metadata()?.len();
+        // SAFETY: ffi
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        check_file_range(file_len, offset, length, page_size.numeric_cast())
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))?;
+
+        // SAFETY: ffi
+        unsafe {
+            let addr = libc::mmap(
+                ptr::null_mut(),
+                length,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                offset.numeric_cast(),
+            );
+            if addr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let addr = NonNull::new_unchecked(addr.cast());
+            Ok(Self { addr, length })
+        }
+    }
+
+    /// Returns the start address of the mapping
+    #[inline]
+    #[must_use]
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.addr.as_ptr()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Writes the modified pages of the mapping back to the file
+    #[inline]
+    pub fn flush(&self) -> io::Result<()> {
+        // SAFETY: ffi
+        let ret = unsafe { libc::msync(self.addr.as_ptr().cast(), self.length, libc::MS_SYNC) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FileMapping {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: ffi
+        unsafe {
+            let ret = libc::munmap(self.addr.as_ptr().cast(), self.length);
+            check_drop(ret, "munmap");
+        }
+    }
+}
+
+/// A memory region which covers a mapped file
+///
+/// Remote writes into the region land in the page cache of the file without a user-space copy.
+/// Call [`FileMapping::flush`] through [`metadata`](MemoryRegion::metadata) to persist them.
+///
+/// The file must not be truncated while it is mapped,
+/// otherwise accessing the truncated pages raises `SIGBUS`.
+pub type FileMappedRegion = MemoryRegion<FileMapping>;
+
+impl MemoryRegion<FileMapping> {
+    /// Maps `length` bytes of `file` starting at `offset` and registers the mapping
+    ///
+    /// The mapping lives as long as the memory region. See [`FileMapping::map`] for the errors.
+    #[inline]
+    pub fn register_file(
+        pd: &ProtectionDomain,
+        file: &File,
+        offset: u64,
+        length: usize,
+        access_flags: AccessFlags,
+    ) -> io::Result<Self> {
+        let mapping = FileMapping::map(file, offset, length)?;
+        let addr = mapping.as_ptr();
+        // SAFETY: the mapping is initialized by the file and is owned by the memory region
+        unsafe { Self::register(pd, addr, length, access_flags, mapping) }
+    }
+}
+
+fn check_file_range(
+    file_len: u64,
+    offset: u64,
+    length: usize,
+    page_size: u64,
+) -> Result<(), String> {
+    if length == 0 {
+        return Err("the mapping length must not be zero".to_owned());
+    }
+    // the page size is a power of two
+    if offset & (page_size - 1) != 0 {
+        return Err(format!(
+            "offset {offset} is not aligned to the page size {page_size}"
+        ));
+    }
+    match offset.checked_add(length.numeric_cast()) {
+        Some(end) if end <= file_len => Ok(()),
+        _ => Err(format!(
+            "the range {offset}+{length} exceeds the file length {file_len}"
+        )),
+    }
+}
+
+/// Panics if `chunk_size` can not be the length of an sge
+fn check_chunk_size(chunk_size: usize) {
+    assert!(chunk_size != 0, "chunk size must not be zero");
+    assert!(
+        u32::try_from(chunk_size).is_ok(),
+        "chunk size does not fit in u32"
+    );
+}
+
+/// Returns the offset and the length of a chunk
+fn chunk_range(length: usize, index: usize, chunk_size: usize) -> Option<(usize, usize)> {
+    check_chunk_size(chunk_size);
+    let offset = index.checked_mul(chunk_size)?;
+    if offset >= length {
+        return None;
+    }
+    Some((offset, chunk_size.min(length.wrapping_sub(offset))))
 }
 
 struct Owner<T> {
     mr: NonNull<C::ibv_mr>,
+    access_flags: AccessFlags,
+    iova: u64,
 
     metadata: T,
 
     _pd: ProtectionDomain,
+    _dm: Option<DeviceMemory>,
 }
 
 /// SAFETY: owned type
@@ -125,7 +484,7 @@ impl<T> Drop for Owner<T> {
         unsafe {
             let mr = self.ffi_ptr();
             let ret = C::ibv_dereg_mr(mr);
-            assert_eq!(ret, 0);
+            check_drop(ret, "ibv_dereg_mr");
         }
     }
 }
@@ -161,3 +520,35 @@ mod flags {
     }
 }
 pub use self::flags::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_bounds() {
+        assert_eq!(chunk_range(100, 0, 32), Some((0, 32)));
+        assert_eq!(chunk_range(100, 3, 32), Some((96, 4)));
+        assert_eq!(chunk_range(100, 4, 32), None);
+        assert_eq!(chunk_range(64, 1, 32), Some((32, 32)));
+        assert_eq!(chunk_range(64, 2, 32), None);
+        assert_eq!(chunk_range(64, usize::MAX, 32), None);
+    }
+
+    #[test]
+    #[should_panic = "chunk size must not be zero"]
+    fn zero_chunk_size() {
+        check_chunk_size(0);
+    }
+
+    #[test]
+    fn file_range() {
+        assert!(check_file_range(8192, 0, 8192, 4096).is_ok());
+        assert!(check_file_range(8192, 4096, 100, 4096).is_ok());
+        assert!(check_file_range(8192, 0, 0, 4096).is_err());
+        assert!(check_file_range(8192, 100, 100, 4096).is_err());
+        let err = check_file_range(4096, 0, 8192, 4096).unwrap_err();
+        assert!(err.contains("exceeds the file length 4096"), "{err}");
+        assert!(check_file_range(u64::MAX, 4096 * 4096, usize::MAX, 4096).is_err());
+    }
+}
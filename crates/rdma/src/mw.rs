@@ -1,5 +1,5 @@
 use crate::bindings as C;
-use crate::error::create_resource;
+use crate::error::{check_drop, create_resource};
 use crate::pd::ProtectionDomain;
 use crate::utils::{c_uint_to_u32, u32_as_c_uint};
 
@@ -52,7 +52,7 @@ impl Drop for Owner {
         unsafe {
             let mw = self.ffi_ptr();
             let ret = C::ibv_dealloc_mw(mw);
-            assert_eq!(ret, 0);
+            check_drop(ret, "ibv_dealloc_mw");
         }
     }
 }
@@ -1,10 +1,12 @@
 use crate::bindings as C;
 use crate::ctx::Context;
-use crate::error::create_resource;
+use crate::error::{check_drop, create_resource};
+use crate::td::ThreadDomain;
 
-use std::io;
-use std::ptr::NonNull;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
 use std::sync::Arc;
+use std::{io, mem};
 
 #[derive(Clone)]
 pub struct ProtectionDomain(Arc<Owner>);
@@ -14,6 +16,16 @@ impl ProtectionDomain {
         self.0.ffi_ptr()
     }
 
+    /// Returns the underlying `ibv_pd` pointer
+    ///
+    /// The pointer is valid as long as the protection domain is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_pd {
+        self.ffi_ptr()
+    }
+
     #[inline]
     pub fn alloc(ctx: &Context) -> io::Result<Self> {
         // SAFETY: ffi
@@ -24,17 +36,100 @@ impl ProtectionDomain {
             )?;
             Arc::new(Owner {
                 pd,
-                _ctx: ctx.clone(),
+                owned: true,
+                _parent: None,
+                ctx: ctx.clone(),
             })
         };
         Ok(Self(owner))
     }
+
+    /// Wraps a protection domain which is allocated by other libraries
+    ///
+    /// If `owned` is true, the protection domain is deallocated when the last handle is dropped.
+    /// Otherwise the caller keeps the control of its lifetime.
+    ///
+    /// # Safety
+    /// + `pd` must be a valid protection domain of `ctx`
+    /// + if `owned` is false, `pd` must outlive all resources created by the returned handle
+    ///
+    /// # Panics
+    /// + if `pd` is null
+    #[inline]
+    #[must_use]
+    pub unsafe fn from_raw(ctx: &Context, pd: *mut C::ibv_pd, owned: bool) -> Self {
+        let pd = NonNull::new(pd).expect("null protection domain");
+        Self(Arc::new(Owner {
+            pd,
+            owned,
+            _parent: None,
+            ctx: ctx.clone(),
+        }))
+    }
+}
+
+/// A parent domain, which combines a protection domain and a thread domain
+///
+/// A parent domain can be used wherever a protection domain is accepted.
+/// Resources created with it belong to the thread domain and
+/// must not be accessed by multiple threads concurrently.
+#[derive(Clone)]
+pub struct ParentDomain {
+    pd: ProtectionDomain,
+    td: ThreadDomain,
+}
+
+impl ParentDomain {
+    /// Allocates a parent domain
+    ///
+    /// Returns `EOPNOTSUPP` if the driver does not support parent domains.
+    #[inline]
+    pub fn alloc(pd: &ProtectionDomain, td: &ThreadDomain) -> io::Result<Self> {
+        let ctx = &pd.0.ctx;
+        // SAFETY: ffi
+        let owner = unsafe {
+            let mut attr: C::ibv_parent_domain_init_attr = mem::zeroed();
+            attr.pd = pd.ffi_ptr();
+            attr.td = td.ffi_ptr();
+            let parent = create_resource(
+                || C::ibv_alloc_parent_domain(ctx.ffi_ptr(), ptr::addr_of_mut!(attr)),
+                || "failed to allocate parent domain",
+            )?;
+            Arc::new(Owner {
+                pd: parent,
+                owned: true,
+                _parent: Some((pd.clone(), td.clone())),
+                ctx: ctx.clone(),
+            })
+        };
+        Ok(Self {
+            pd: ProtectionDomain(owner),
+            td: td.clone(),
+        })
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn thread_domain(&self) -> &ThreadDomain {
+        &self.td
+    }
+}
+
+impl Deref for ParentDomain {
+    type Target = ProtectionDomain;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.pd
+    }
 }
 
 struct Owner {
     pd: NonNull<C::ibv_pd>,
+    owned: bool,
 
-    _ctx: Context,
+    _parent: Option<(ProtectionDomain, ThreadDomain)>,
+    ctx: Context,
 }
 
 /// SAFETY: owned type
@@ -50,11 +145,14 @@ impl Owner {
 
 impl Drop for Owner {
     fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
         // SAFETY: ffi
         unsafe {
             let pd = self.ffi_ptr();
             let ret = C::ibv_dealloc_pd(pd);
-            assert_eq!(ret, 0);
+            check_drop(ret, "ibv_dealloc_pd");
         }
     }
 }
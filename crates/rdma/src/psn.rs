@@ -0,0 +1,34 @@
+use crate::error::last_error;
+
+/// The packet sequence number is a 24-bit field.
+pub(crate) const PSN_MASK: u32 = 0x00ff_ffff;
+
+/// Returns a random 24-bit packet sequence number.
+///
+/// A connection should not reuse a fixed initial PSN.
+/// If a QPN is reused and a stale packet of the previous connection arrives,
+/// a predictable PSN makes it look valid, which enables replay and corrupts the stream.
+///
+/// # Panics
+/// + if the system random source is unavailable
+#[inline]
+#[must_use]
+pub fn random_psn() -> u32 {
+    let mut bytes = [0u8; 4];
+    // SAFETY: ffi
+    let ret = unsafe { libc::getrandom(bytes.as_mut_ptr().cast(), bytes.len(), 0) };
+    assert!(ret >= 0, "failed to get random bytes: {}", last_error());
+    u32::from_ne_bytes(bytes) & PSN_MASK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_psn_range() {
+        for _ in 0..64 {
+            assert!(random_psn() <= PSN_MASK);
+        }
+    }
+}
@@ -2,10 +2,12 @@ use crate::ah::AddressHandleOptions;
 use crate::bindings as C;
 use crate::cq::CompletionQueue;
 use crate::ctx::Context;
-use crate::device::Mtu;
-use crate::error::{create_resource, from_errno, get_errno, set_errno};
+use crate::device::{DeviceAttr, Gid, Mtu};
+use crate::error::{check_drop, create_resource, from_errno, get_errno, set_errno};
 use crate::mr::AccessFlags;
 use crate::pd::ProtectionDomain;
+use crate::psn::PSN_MASK;
+use crate::qp_ex::{SendOpsFlags, WrBatch};
 use crate::srq::SharedReceiveQueue;
 use crate::utils::{bool_to_c_int, c_uint_to_u32, ptr_as_mut, u32_as_c_uint};
 use crate::utils::{usize_to_void_ptr, void_ptr_to_usize};
@@ -15,7 +17,11 @@ use std::mem::MaybeUninit;
 use std::os::raw::{c_int, c_uint};
 use std::ptr::{self, NonNull};
 use std::sync::Arc;
-use std::{io, mem};
+use std::time::{Duration, Instant};
+use std::{io, mem, thread};
+
+use numeric_cast::NumericCast;
+use parking_lot::Mutex;
 
 #[derive(Clone)]
 pub struct QueuePair(Arc<Owner>);
@@ -25,6 +31,16 @@ impl QueuePair {
         self.0.ffi_ptr()
     }
 
+    /// Returns the underlying `ibv_qp` pointer
+    ///
+    /// The pointer is valid as long as the queue pair is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_qp {
+        self.ffi_ptr()
+    }
+
     #[inline]
     #[must_use]
     pub fn options() -> QueuePairOptions {
@@ -32,19 +48,59 @@ impl QueuePair {
     }
 
     #[inline]
-    pub fn create(ctx: &Context, mut options: QueuePairOptions) -> io::Result<Self> {
+    pub fn create(ctx: &Context, options: QueuePairOptions) -> io::Result<Self> {
         // SAFETY: ffi
-        let owner = unsafe {
+        unsafe {
+            Self::create_with(ctx, options, |context, qp_attr| {
+                C::ibv_create_qp_ex(context, qp_attr)
+            })
+        }
+    }
+
+    /// Creates a queue pair with a custom create function, such as a vendor extension
+    ///
+    /// # Safety
+    /// `create` must return null with errno set on failure,
+    /// or a queue pair which is created from `qp_attr`
+    pub(crate) unsafe fn create_with(
+        ctx: &Context,
+        mut options: QueuePairOptions,
+        create: impl FnOnce(*mut C::ibv_context, *mut C::ibv_qp_init_attr_ex) -> *mut C::ibv_qp,
+    ) -> io::Result<Self> {
+        let owner = {
             let context = ctx.ffi_ptr();
             let qp_attr = &mut options.attr;
 
-            let qp = create_resource(
-                || C::ibv_create_qp_ex(context, qp_attr),
+            let qp = match create_resource(
+                || create(context, qp_attr),
                 || "failed to create queue pair",
-            )?;
+            ) {
+                Ok(qp) => qp,
+                Err(err) => {
+                    // the driver rejects a capacity beyond the device limits with a bare errno
+                    let cap = QueuePairCapacity::from_ctype_ref(&qp_attr.cap);
+                    if let Ok(device_attr) = DeviceAttr::query(ctx) {
+                        cap.validate(&device_attr)?;
+                    }
+                    return Err(err.into());
+                }
+            };
+
+            // the driver updates `cap` to the actual values
+            let cap = QueuePairCapacity::from_ctype_ref(&qp_attr.cap).clone();
+
+            for cq in [&options.send_cq, &options.recv_cq].into_iter().flatten() {
+                cq.add_qp_ref();
+            }
 
             Arc::new(Owner {
                 qp,
+                cap,
+                send_ops: options.send_ops,
+                wr_lock: Mutex::new(()),
+                reset_before_destroy: options.reset_before_destroy,
+                destroyed: false,
+                ctx: ctx.clone(),
                 _pd: options.pd,
                 send_cq: options.send_cq,
                 recv_cq: options.recv_cq,
@@ -54,6 +110,31 @@ impl QueuePair {
         Ok(Self(owner))
     }
 
+    #[inline]
+    #[must_use]
+    pub fn context(&self) -> &Context {
+        &self.0.ctx
+    }
+
+    /// Returns the capacity of the queue pair
+    ///
+    /// These are the actual values granted by the driver when the queue pair is created,
+    /// which may be larger than requested.
+    #[inline]
+    #[must_use]
+    pub fn capacity(&self) -> QueuePairCapacity {
+        self.0.cap.clone()
+    }
+
+    /// Returns the maximum size of inline data of the send queue
+    ///
+    /// This is the value granted by the driver, which may be larger than requested.
+    #[inline]
+    #[must_use]
+    pub fn max_inline_data(&self) -> u32 {
+        self.0.cap.max_inline_data
+    }
+
     #[inline]
     #[must_use]
     pub fn qp_num(&self) -> u32 {
@@ -72,10 +153,21 @@ impl QueuePair {
 
     /// # Safety
     /// TODO
+    ///
+    /// # Panics
+    /// + (debug) if any request in the list has more sges than `max_send_sge`
     #[inline]
     pub unsafe fn post_send(&self, send_wr: &SendRequest) -> io::Result<()> {
         let qp = self.ffi_ptr();
         let wr: *mut C::ibv_send_wr = ptr_as_mut(send_wr).cast();
+        if cfg!(debug_assertions) {
+            let max_sge = self.0.cap.max_send_sge;
+            let mut p = wr;
+            while !p.is_null() {
+                check_num_sge((*p).num_sge, max_sge, "max_send_sge");
+                p = (*p).next;
+            }
+        }
         let mut bad_wr: *mut C::ibv_send_wr = ptr::null_mut();
         set_errno(0);
         let ret = C::ibv_post_send(qp, wr, &mut bad_wr);
@@ -89,12 +181,29 @@ impl QueuePair {
         Ok(())
     }
 
+    /// Posts a list of receive requests
+    ///
+    /// Receives may be posted as soon as the queue pair is in the `Initialize` state.
+    /// Posting them before moving to `ReadyToReceive` ensures that the first messages
+    /// of the peer find a buffer instead of being answered with RNR NAKs.
+    ///
     /// # Safety
     /// TODO
+    ///
+    /// # Panics
+    /// + (debug) if any request in the list has more sges than `max_recv_sge`
     #[inline]
     pub unsafe fn post_recv(&self, recv_wr: &RecvRequest) -> io::Result<()> {
         let qp = self.ffi_ptr();
         let wr: *mut C::ibv_recv_wr = ptr_as_mut(recv_wr).cast();
+        if cfg!(debug_assertions) {
+            let max_sge = self.0.cap.max_recv_sge;
+            let mut p = wr;
+            while !p.is_null() {
+                check_num_sge((*p).num_sge, max_sge, "max_recv_sge");
+                p = (*p).next;
+            }
+        }
         let mut bad_wr: *mut C::ibv_recv_wr = ptr::null_mut();
         set_errno(0);
         let ret = C::ibv_post_recv(qp, wr, &mut bad_wr);
@@ -108,11 +217,153 @@ impl QueuePair {
         Ok(())
     }
 
+    /// Destroys the queue pair explicitly
+    ///
+    /// Returns `EBUSY` if other handles of the queue pair still exist.
+    /// In that case, this handle is dropped and the queue pair is destroyed with the last handle.
+    ///
+    /// If the driver fails to destroy the queue pair, the error is returned and
+    /// the queue pair is leaked together with the resources it references.
+    ///
+    /// See [`QueuePairOptions::reset_before_destroy`].
+    #[inline]
+    pub fn destroy(self) -> io::Result<()> {
+        let Ok(mut owner) = Arc::try_unwrap(self.0) else {
+            return Err(from_errno(libc::EBUSY));
+        };
+        let ret = owner.destroy_qp();
+        if ret != 0 {
+            mem::forget(owner);
+            return Err(from_errno(ret));
+        }
+        owner.destroyed = true;
+        owner.release_cq_refs();
+        Ok(())
+    }
+
+    /// Queries the ECE options of the queue pair
+    ///
+    /// Before the connection is established, this returns the options supported by the local device,
+    /// which should be sent to the peer. Afterwards, it returns the options accepted by both sides.
+    /// Returns `EOPNOTSUPP` if the driver does not support Enhanced Connection Establishment.
+    #[inline]
+    pub fn query_ece(&self) -> io::Result<Ece> {
+        // SAFETY: ffi
+        unsafe {
+            let mut ece: C::ibv_ece = mem::zeroed();
+            let ret = C::ibv_query_ece(self.ffi_ptr(), ptr::addr_of_mut!(ece));
+            if ret != 0 {
+                return Err(from_errno(ret.abs()));
+            }
+            Ok(Ece {
+                vendor_id: ece.vendor_id,
+                options: ece.options,
+            })
+        }
+    }
+
+    /// Sets the ECE options which are received from the peer
+    ///
+    /// This must be called before the queue pair is modified to RTR.
+    /// The driver keeps only the options which are supported by both sides.
+    /// Returns `EOPNOTSUPP` if the driver does not support Enhanced Connection Establishment.
+    #[inline]
+    pub fn set_ece(&self, ece: Ece) -> io::Result<()> {
+        // SAFETY: ffi
+        unsafe {
+            let mut ece = C::ibv_ece {
+                vendor_id: ece.vendor_id,
+                options: ece.options,
+                comp_mask: 0,
+            };
+            let ret = C::ibv_set_ece(self.ffi_ptr(), ptr::addr_of_mut!(ece));
+            if ret != 0 {
+                return Err(from_errno(ret.abs()));
+            }
+            Ok(())
+        }
+    }
+
+    /// Attaches the queue pair to the multicast group `gid`
+    ///
+    /// The queue pair must be UD, otherwise an `InvalidInput` error is returned.
+    /// On InfiniBand, the group must be created by the subnet manager before it can be attached,
+    /// and `lid` is the LID which the subnet manager assigned to the group.
+    /// On `RoCE`, the group is joined by the IP stack and `lid` is ignored.
+    #[inline]
+    pub fn attach_mcast(&self, gid: Gid, lid: u16) -> io::Result<()> {
+        self.check_ud("multicast requires a UD queue pair")?;
+        // SAFETY: ffi
+        unsafe {
+            let gid_ptr = ptr::addr_of!(gid).cast::<C::ibv_gid>();
+            let ret = C::ibv_attach_mcast(self.ffi_ptr(), gid_ptr, lid);
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+            Ok(())
+        }
+    }
+
+    /// Detaches the queue pair from a multicast group
+    ///
+    /// See [`QueuePair::attach_mcast`].
+    #[inline]
+    pub fn detach_mcast(&self, gid: Gid, lid: u16) -> io::Result<()> {
+        self.check_ud("multicast requires a UD queue pair")?;
+        // SAFETY: ffi
+        unsafe {
+            let gid_ptr = ptr::addr_of!(gid).cast::<C::ibv_gid>();
+            let ret = C::ibv_detach_mcast(self.ffi_ptr(), gid_ptr, lid);
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+            Ok(())
+        }
+    }
+
+    fn check_ud(&self, msg: &'static str) -> io::Result<()> {
+        // SAFETY: reading a field which is immutable after creation
+        let qp_type = unsafe { (*self.ffi_ptr()).qp_type };
+        if qp_type != C::IBV_QPT_UD {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        Ok(())
+    }
+
+    /// Starts a batch of work requests which is posted with a single doorbell
+    ///
+    /// The queue pair must be created with [`QueuePairOptions::send_ops_flags`],
+    /// otherwise `EOPNOTSUPP` is returned.
+    /// Only one batch of a queue pair can be active at a time.
+    /// This method blocks until the previous batch is completed or dropped.
+    #[inline]
+    pub fn start_wr(&self) -> io::Result<WrBatch<'_>> {
+        let guard = self.0.wr_lock.lock();
+        WrBatch::start(self, self.0.send_ops, guard)
+    }
+
+    /// Modifies the attributes of the queue pair
+    ///
+    /// For RC, UC and UD queue pairs, the attribute mask is checked against
+    /// the state transition table of the IB spec before calling into the driver.
+    /// An illegal or missing attribute results in an `InvalidInput` error naming it.
     #[inline]
     pub fn modify(&self, mut options: ModifyOptions) -> io::Result<()> {
         let qp = self.ffi_ptr();
         // SAFETY: ffi
         unsafe {
+            let cur_state = (*qp).state;
+            let next_state = if options.mask & C::IBV_QP_STATE != 0 {
+                ptr::addr_of!((*options.attr.as_ptr()).qp_state).read()
+            } else {
+                cur_state
+            };
+            if let Err(msg) = check_modify_mask((*qp).qp_type, cur_state, next_state, options.mask)
+            {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+            self.check_device_limits(&options)?;
+
             let attr_mask: c_int = mem::transmute(options.mask);
             let attr = options.attr.as_mut_ptr();
             let ret = C::ibv_modify_qp(qp, attr, attr_mask);
@@ -123,6 +374,131 @@ impl QueuePair {
         }
     }
 
+    /// Moves the queue pair from its current state to `to`
+    ///
+    /// The current state is queried from the driver. The attribute mask of each transition
+    /// is inferred from the state transition table of the IB spec: the required and optional
+    /// attributes are taken from `params`, and the others are ignored,
+    /// so the same `params` can serve several transitions.
+    ///
+    /// A forward move along `Reset`, `Initialize`, `ReadyToReceive` and `ReadyToSend`
+    /// goes through each state in between. Other moves must be a single legal transition.
+    ///
+    /// Only RC, UC and UD queue pairs are supported.
+    ///
+    /// # Errors
+    /// + `InvalidInput` if the transition is illegal or `params` lacks a required attribute,
+    ///   such as `RTR transition requires dest_qp_num`
+    #[inline]
+    pub fn transition(&self, to: QueuePairState, params: &TransitionParams) -> io::Result<()> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+        // SAFETY: ffi
+        let qp_type = unsafe { (*self.ffi_ptr()).qp_type };
+        let ty = qp_type_index(qp_type).ok_or_else(|| {
+            invalid("transition only supports RC, UC and UD queue pairs".to_owned())
+        })?;
+
+        let mut cur = {
+            let mut options = QueryOptions::default();
+            options.qp_state();
+            self.query(options)?.attr.qp_state
+        };
+        for next in transition_path(cur, to.to_c_uint()) {
+            let options = params.transition_options(ty, cur, next).map_err(invalid)?;
+            self.modify(options)?;
+            cur = next;
+        }
+        Ok(())
+    }
+
+    /// Waits until all posted send requests are complete
+    ///
+    /// A queue pair in RTS is moved to SQD (Send Queue Drained) first: it stops processing
+    /// new send requests, and the requests which are already posted are completed.
+    /// Then the queue pair is queried until the send queue is drained.
+    /// The completions are still reported on the send completion queue.
+    ///
+    /// The queue pair stays in SQD. It can be moved back to RTS to resume,
+    /// or be destroyed without dropping in-flight data.
+    ///
+    /// # Errors
+    /// + `InvalidInput` if the queue pair is not in RTS or SQD
+    /// + `TimedOut` if the send queue is not drained within `timeout`
+    ///
+    /// A `timeout` which is too large to represent a deadline waits forever.
+    #[inline]
+    pub fn wait_drained(&self, timeout: Duration) -> io::Result<()> {
+        // `None` means no deadline
+        let deadline = Instant::now().checked_add(timeout);
+
+        let mut query = QueryOptions::default();
+        query.qp_state();
+
+        match self.query(query)?.qp_state() {
+            Some(QueuePairState::ReadyToSend) => {
+                let mut options = ModifyOptions::default();
+                options.qp_state(QueuePairState::SendQueueDrained);
+                self.modify(options)?;
+            }
+            Some(QueuePairState::SendQueueDrained) => {}
+            state => {
+                let msg = format!("can not drain a queue pair in state {state:?}");
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+        }
+
+        while self.query(query)?.sq_draining() == Some(true) {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                let msg = "timed out waiting for the send queue to drain";
+                return Err(io::Error::new(io::ErrorKind::TimedOut, msg));
+            }
+            thread::sleep(Duration::from_micros(100));
+        }
+        Ok(())
+    }
+
+    /// Checks the depths of RDMA reads and atomics and the rate limit against the device attributes
+    fn check_device_limits(&self, options: &ModifyOptions) -> io::Result<()> {
+        let mask = options.mask
+            & (C::IBV_QP_MAX_QP_RD_ATOMIC | C::IBV_QP_MAX_DEST_RD_ATOMIC | C::IBV_QP_RATE_LIMIT);
+        if mask == 0 {
+            return Ok(());
+        }
+        let device_attr = DeviceAttr::query(&self.0.ctx)?;
+        let attr = options.attr.as_ptr();
+        let check = |name, value: u8, max: u32| {
+            if u32::from(value) > max {
+                let msg = format!("{name} {value} exceeds the device maximum {max}");
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+            Ok(())
+        };
+        // SAFETY: the fields are initialized when their bits are set in the mask
+        unsafe {
+            if mask & C::IBV_QP_MAX_QP_RD_ATOMIC != 0 {
+                let value = ptr::addr_of!((*attr).max_rd_atomic).read();
+                check("max_rd_atomic", value, device_attr.max_qp_init_rd_atom())?;
+            }
+            if mask & C::IBV_QP_MAX_DEST_RD_ATOMIC != 0 {
+                let value = ptr::addr_of!((*attr).max_dest_rd_atomic).read();
+                check("max_dest_rd_atomic", value, device_attr.max_qp_rd_atom())?;
+            }
+            if mask & C::IBV_QP_RATE_LIMIT != 0 {
+                let value = ptr::addr_of!((*attr).rate_limit).read();
+                let (min, max) = device_attr.qp_rate_limit_range();
+                // zero removes the rate limit
+                if value != 0 && !(min..=max).contains(&value) {
+                    let msg = format!(
+                        "rate_limit {value} kbps is out of the device range {min}..={max} kbps"
+                    );
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+                }
+            }
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn query(&self, options: QueryOptions) -> io::Result<QueuePairAttr> {
         let qp = self.ffi_ptr();
@@ -155,7 +531,13 @@ impl QueuePair {
 
 struct Owner {
     qp: NonNull<C::ibv_qp>,
+    cap: QueuePairCapacity,
+    send_ops: SendOpsFlags,
+    wr_lock: Mutex<()>,
+    reset_before_destroy: bool,
+    destroyed: bool,
 
+    ctx: Context,
     _pd: Option<ProtectionDomain>,
     send_cq: Option<CompletionQueue>,
     recv_cq: Option<CompletionQueue>,
@@ -171,20 +553,59 @@ impl Owner {
     fn ffi_ptr(&self) -> *mut C::ibv_qp {
         self.qp.as_ptr()
     }
+
+    /// Destroys the queue pair, modifying it to RESET first if requested
+    fn destroy_qp(&self) -> c_int {
+        let qp = self.ffi_ptr();
+        // SAFETY: ffi
+        unsafe {
+            if self.reset_before_destroy {
+                let mut attr: C::ibv_qp_attr = mem::zeroed();
+                attr.qp_state = C::IBV_QPS_RESET;
+                let attr_mask: c_int = C::IBV_QP_STATE.numeric_cast();
+                // best effort: the queue pair is destroyed even if the transition fails
+                let _ = C::ibv_modify_qp(qp, ptr::addr_of_mut!(attr), attr_mask);
+            }
+            C::ibv_destroy_qp(qp)
+        }
+    }
+
+    /// Releases the references to the completion queues after the queue pair is destroyed
+    fn release_cq_refs(&self) {
+        for cq in [&self.send_cq, &self.recv_cq].into_iter().flatten() {
+            cq.del_qp_ref();
+        }
+    }
 }
 
 impl Drop for Owner {
     fn drop(&mut self) {
-        // SAFETY: ffi
-        unsafe {
-            let qp: *mut C::ibv_qp = self.ffi_ptr();
-            let ret = C::ibv_destroy_qp(qp);
-            assert_eq!(ret, 0);
+        if self.destroyed {
+            return;
+        }
+        let ret = self.destroy_qp();
+        check_drop(ret, "ibv_destroy_qp");
+        if ret == 0 {
+            self.release_cq_refs();
         }
     }
 }
 
-#[derive(Clone)]
+/// Enhanced Connection Establishment options
+///
+/// ECE lets the peers of a `RoCE` connection negotiate vendor specific features,
+/// such as the congestion control algorithm, during the connection setup.
+/// The options are opaque to the library and only meaningful to the device vendor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ece {
+    /// The IEEE vendor id of the device
+    pub vendor_id: u32,
+    /// The vendor specific options
+    pub options: u32,
+}
+
+#[derive(Debug, Clone)]
 #[repr(C)]
 pub struct QueuePairCapacity {
     pub max_send_wr: u32,
@@ -203,6 +624,33 @@ impl Default for QueuePairCapacity {
 }
 
 impl QueuePairCapacity {
+    /// Checks the capacity against [`DeviceAttr::max_qp_wr`] and [`DeviceAttr::max_sge`]
+    ///
+    /// The limit of `max_inline_data` is not reported by the device, so it is not checked.
+    ///
+    /// # Errors
+    /// + `InvalidInput` naming the first field which exceeds the device maximum
+    #[inline]
+    pub fn validate(&self, device_attr: &DeviceAttr) -> io::Result<()> {
+        self.check_limits(device_attr.max_qp_wr(), device_attr.max_sge())
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))
+    }
+
+    fn check_limits(&self, max_wr: u32, max_sge: u32) -> Result<(), String> {
+        let fields = [
+            ("max_send_wr", self.max_send_wr, max_wr),
+            ("max_recv_wr", self.max_recv_wr, max_wr),
+            ("max_send_sge", self.max_send_sge, max_sge),
+            ("max_recv_sge", self.max_recv_sge, max_sge),
+        ];
+        for (name, value, max) in fields {
+            if value > max {
+                return Err(format!("{name} {value} exceeds the device maximum {max}"));
+            }
+        }
+        Ok(())
+    }
+
     fn into_ctype(self) -> C::ibv_qp_cap {
         // SAFETY: same repr
         unsafe { mem::transmute(self) }
@@ -220,6 +668,8 @@ pub struct QueuePairOptions {
     recv_cq: Option<CompletionQueue>,
     pd: Option<ProtectionDomain>,
     srq: Option<SharedReceiveQueue>,
+    send_ops: SendOpsFlags,
+    reset_before_destroy: bool,
 }
 
 // SAFETY: owned type
@@ -237,6 +687,8 @@ impl Default for QueuePairOptions {
             recv_cq: None,
             pd: None,
             srq: None,
+            send_ops: SendOpsFlags::empty(),
+            reset_before_destroy: false,
         }
     }
 }
@@ -271,12 +723,44 @@ impl QueuePairOptions {
         self
     }
 
+    /// Sets whether every send request generates a completion.
+    ///
+    /// If `sq_sig_all` is false, only the requests with [`SendFlags::SIGNALED`](crate::wr::SendFlags::SIGNALED)
+    /// generate completions. A signaled completion also means that all earlier
+    /// unsignaled requests of the send queue have completed, so signaling every Nth
+    /// request (see [`SignalInterval`](crate::wr::SignalInterval)) saves completion queue bandwidth.
+    /// The interval must not exceed `max_send_wr`, otherwise the send queue fills up
+    /// with requests which are never reported as completed.
     #[inline]
     pub fn sq_sig_all(&mut self, sq_sig_all: bool) -> &mut Self {
         self.attr.sq_sig_all = bool_to_c_int(sq_sig_all);
         self
     }
 
+    /// Enables the extended work request API for the operations
+    ///
+    /// See [`QueuePair::start_wr`].
+    #[inline]
+    pub fn send_ops_flags(&mut self, send_ops: SendOpsFlags) -> &mut Self {
+        self.attr.send_ops_flags = u64::from(send_ops.bits());
+        self.attr.comp_mask |= C::IBV_QP_INIT_ATTR_SEND_OPS_FLAGS;
+        self.send_ops = send_ops;
+        self
+    }
+
+    /// Sets whether the queue pair is modified to RESET before it is destroyed
+    ///
+    /// Destroying a queue pair in any state is allowed by the verbs API,
+    /// but some drivers (notably `rdma_rxe`) do not flush the hardware state cleanly
+    /// unless the queue pair is reset first, which leaks resources until the process exits.
+    /// The transition applies to both [`QueuePair::destroy`] and dropping the last handle.
+    /// A failed transition is ignored and the queue pair is destroyed anyway.
+    #[inline]
+    pub fn reset_before_destroy(&mut self, reset_before_destroy: bool) -> &mut Self {
+        self.reset_before_destroy = reset_before_destroy;
+        self
+    }
+
     #[inline]
     pub fn cap(&mut self, cap: QueuePairCapacity) -> &mut Self {
         self.attr.cap = cap.into_ctype();
@@ -300,6 +784,26 @@ impl QueuePairOptions {
         self.srq = Some(srq.clone());
         self
     }
+
+    /// Sets both the shared receive queue and the receive completion queue
+    ///
+    /// Some vendor queue pairs, such as DC targets, require both.
+    #[cfg(feature = "mlx5")]
+    pub(crate) fn srq_with_cq(
+        &mut self,
+        srq: &SharedReceiveQueue,
+        recv_cq: &CompletionQueue,
+    ) -> &mut Self {
+        self.srq(srq);
+        self.attr.recv_cq = C::ibv_cq_ex_to_cq(recv_cq.ffi_ptr());
+        self.recv_cq = Some(recv_cq.clone());
+        self
+    }
+
+    #[cfg(feature = "mlx5")]
+    pub(crate) fn get_send_ops(&self) -> SendOpsFlags {
+        self.send_ops
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -342,7 +846,8 @@ impl Default for ModifyOptions {
 }
 
 macro_rules! modify_option {
-    ($mask: ident, $field: ident, $ty: ty, $($cvt:tt)+) => {
+    ($(#[$attr:meta])* $mask: ident, $field: ident, $ty: ty, $($cvt:tt)+) => {
+        $(#[$attr])*
         #[inline]
         pub fn $field(&mut self, $field: $ty) -> &mut Self {
             // SAFETY: write uninit field
@@ -370,7 +875,18 @@ impl ModifyOptions {
     );
     modify_option!(IBV_QP_PATH_MTU, path_mtu, Mtu, path_mtu.to_c_uint());
     modify_option!(IBV_QP_DEST_QPN, dest_qp_num, u32, dest_qp_num);
-    modify_option!(IBV_QP_RQ_PSN, rq_psn, u32, rq_psn);
+    modify_option!(
+        /// Sets the PSN of the receive queue.
+        ///
+        /// The PSN is a 24-bit value. The higher bits are discarded.
+        ///
+        /// # Panics
+        /// + (debug) if `rq_psn` does not fit in 24 bits
+        IBV_QP_RQ_PSN,
+        rq_psn,
+        u32,
+        checked_psn(rq_psn)
+    );
     modify_option!(
         IBV_QP_MAX_DEST_RD_ATOMIC,
         max_dest_rd_atomic,
@@ -387,8 +903,437 @@ impl ModifyOptions {
     modify_option!(IBV_QP_TIMEOUT, timeout, u8, timeout);
     modify_option!(IBV_QP_RETRY_CNT, retry_cnt, u8, retry_cnt);
     modify_option!(IBV_QP_RNR_RETRY, rnr_retry, u8, rnr_retry);
-    modify_option!(IBV_QP_SQ_PSN, sq_psn, u32, sq_psn);
+    modify_option!(
+        /// Sets the PSN of the send queue.
+        ///
+        /// The PSN is a 24-bit value. The higher bits are discarded.
+        ///
+        /// # Panics
+        /// + (debug) if `sq_psn` does not fit in 24 bits
+        IBV_QP_SQ_PSN,
+        sq_psn,
+        u32,
+        checked_psn(sq_psn)
+    );
     modify_option!(IBV_QP_MAX_QP_RD_ATOMIC, max_rd_atomic, u8, max_rd_atomic);
+    modify_option!(
+        /// Sets the maximum send rate of the queue pair in kbps.
+        ///
+        /// Zero removes the rate limit. Otherwise the rate must be in
+        /// [`DeviceAttr::qp_rate_limit_range`], which is empty if the device does not support packet pacing.
+        /// It can be set on the transitions from RTR to RTS and from RTS to RTS.
+        IBV_QP_RATE_LIMIT,
+        rate_limit,
+        u32,
+        rate_limit
+    );
+    modify_option!(
+        /// Sets the path migration state of RC and UC queue pairs.
+        ///
+        /// Setting `Rearm` asks the device to arm the alternate path, after which the state becomes `Armed`.
+        /// It can be set on the transitions from RTR to RTS, from RTS to RTS and from SQD to RTS.
+        IBV_QP_PATH_MIG_STATE,
+        path_mig_state,
+        PathMigrationState,
+        path_mig_state.to_c_uint()
+    );
+    /// Sets the alternate path for automatic path migration (APM) of RC and UC queue pairs.
+    ///
+    /// The alternate path is used when the primary path fails and the
+    /// [`path_mig_state`](Self::path_mig_state) is `Armed`.
+    /// It can be set on the transitions from INIT to RTR, from RTR to RTS, from RTS to RTS and from SQD to RTS.
+    #[inline]
+    pub fn alt_path(
+        &mut self,
+        ah_attr: AddressHandleOptions,
+        alt_port: u8,
+        alt_timeout: u8,
+        alt_pkey_index: u16,
+    ) -> &mut Self {
+        let attr = self.attr.as_mut_ptr();
+        // SAFETY: write uninit fields
+        unsafe { write_alt_path(attr, ah_attr, alt_port, alt_timeout, alt_pkey_index) }
+        self.mask |= C::IBV_QP_ALT_PATH;
+        self
+    }
+    modify_option!(
+        /// Requests an [`SqDrained`](crate::event::AsyncEventType::SqDrained) event
+        /// when the send queue is drained, see [`AsyncEvent::is_sq_drained_of`](crate::event::AsyncEvent::is_sq_drained_of).
+        ///
+        /// It can be set on the transition from RTS to SQD.
+        IBV_QP_EN_SQD_ASYNC_NOTIFY,
+        en_sqd_async_notify,
+        bool,
+        u8::from(en_sqd_async_notify)
+    );
+}
+
+/// Writes the alternate path attributes
+///
+/// # Safety
+/// `attr` must be valid for writes
+unsafe fn write_alt_path(
+    attr: *mut C::ibv_qp_attr,
+    ah_attr: AddressHandleOptions,
+    alt_port: u8,
+    alt_timeout: u8,
+    alt_pkey_index: u16,
+) {
+    ptr::addr_of_mut!((*attr).alt_ah_attr).write(ah_attr.into_ctype());
+    ptr::addr_of_mut!((*attr).alt_port_num).write(alt_port);
+    ptr::addr_of_mut!((*attr).alt_timeout).write(alt_timeout);
+    ptr::addr_of_mut!((*attr).alt_pkey_index).write(alt_pkey_index);
+}
+
+/// Attributes of state transitions, see [`QueuePair::transition`]
+///
+/// The state is not a parameter: it is the target of the transition.
+#[repr(C)]
+pub struct TransitionParams {
+    mask: C::ibv_qp_attr_mask,
+    attr: MaybeUninit<C::ibv_qp_attr>,
+}
+
+// SAFETY: owned type
+unsafe impl Send for TransitionParams {}
+// SAFETY: owned type
+unsafe impl Sync for TransitionParams {}
+
+impl Default for TransitionParams {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            mask: 0,
+            attr: MaybeUninit::uninit(),
+        }
+    }
+}
+
+impl TransitionParams {
+    modify_option!(IBV_QP_PKEY_INDEX, pkey_index, u16, pkey_index);
+    modify_option!(IBV_QP_PORT, port_num, u8, port_num);
+    modify_option!(IBV_QP_QKEY, qkey, u32, qkey);
+    modify_option!(
+        IBV_QP_ACCESS_FLAGS,
+        qp_access_flags,
+        AccessFlags,
+        qp_access_flags.to_c_uint()
+    );
+    modify_option!(IBV_QP_PATH_MTU, path_mtu, Mtu, path_mtu.to_c_uint());
+    modify_option!(IBV_QP_DEST_QPN, dest_qp_num, u32, dest_qp_num);
+    modify_option!(
+        /// See [`ModifyOptions::rq_psn`]
+        IBV_QP_RQ_PSN,
+        rq_psn,
+        u32,
+        checked_psn(rq_psn)
+    );
+    modify_option!(
+        IBV_QP_MAX_DEST_RD_ATOMIC,
+        max_dest_rd_atomic,
+        u8,
+        max_dest_rd_atomic
+    );
+    modify_option!(IBV_QP_MIN_RNR_TIMER, min_rnr_timer, u8, min_rnr_timer);
+    modify_option!(
+        IBV_QP_AV,
+        ah_attr,
+        AddressHandleOptions,
+        ah_attr.into_ctype()
+    );
+    modify_option!(IBV_QP_TIMEOUT, timeout, u8, timeout);
+    modify_option!(IBV_QP_RETRY_CNT, retry_cnt, u8, retry_cnt);
+    modify_option!(IBV_QP_RNR_RETRY, rnr_retry, u8, rnr_retry);
+    modify_option!(
+        /// See [`ModifyOptions::sq_psn`]
+        IBV_QP_SQ_PSN,
+        sq_psn,
+        u32,
+        checked_psn(sq_psn)
+    );
+    modify_option!(IBV_QP_MAX_QP_RD_ATOMIC, max_rd_atomic, u8, max_rd_atomic);
+    modify_option!(
+        /// See [`ModifyOptions::rate_limit`]
+        IBV_QP_RATE_LIMIT,
+        rate_limit,
+        u32,
+        rate_limit
+    );
+    modify_option!(
+        /// See [`ModifyOptions::path_mig_state`]
+        IBV_QP_PATH_MIG_STATE,
+        path_mig_state,
+        PathMigrationState,
+        path_mig_state.to_c_uint()
+    );
+
+    /// See [`ModifyOptions::alt_path`]
+    #[inline]
+    pub fn alt_path(
+        &mut self,
+        ah_attr: AddressHandleOptions,
+        alt_port: u8,
+        alt_timeout: u8,
+        alt_pkey_index: u16,
+    ) -> &mut Self {
+        let attr = self.attr.as_mut_ptr();
+        // SAFETY: write uninit fields
+        unsafe { write_alt_path(attr, ah_attr, alt_port, alt_timeout, alt_pkey_index) }
+        self.mask |= C::IBV_QP_ALT_PATH;
+        self
+    }
+
+    modify_option!(
+        /// See [`ModifyOptions::en_sqd_async_notify`]
+        IBV_QP_EN_SQD_ASYNC_NOTIFY,
+        en_sqd_async_notify,
+        bool,
+        u8::from(en_sqd_async_notify)
+    );
+
+    /// Returns the options of one transition, which only contain the attributes of the transition
+    fn transition_options(
+        &self,
+        ty: usize,
+        cur: c_uint,
+        next: c_uint,
+    ) -> Result<ModifyOptions, String> {
+        let Some((req, opt)) = qp_transition_attrs(cur, next, ty) else {
+            let (cur, next) = (state_name(cur), state_name(next));
+            return Err(format!(
+                "illegal queue pair state transition: {cur} -> {next}"
+            ));
+        };
+        let missing = req & !self.mask;
+        if missing != 0 {
+            let names = qp_attr_names(missing);
+            return Err(format!("{} transition requires {names}", state_name(next)));
+        }
+        let mut options = ModifyOptions {
+            mask: self.mask & (req | opt),
+            attr: self.attr,
+        };
+        options.qp_state(QueuePairState::from_c_uint(next));
+        Ok(options)
+    }
+}
+
+/// Returns the states which a transition from `cur` to `to` goes through, ending with `to`
+fn transition_path(cur: c_uint, to: c_uint) -> Vec<c_uint> {
+    const FORWARD: [c_uint; 4] = [
+        C::IBV_QPS_RESET,
+        C::IBV_QPS_INIT,
+        C::IBV_QPS_RTR,
+        C::IBV_QPS_RTS,
+    ];
+    let pos = |state| FORWARD.iter().position(|&s| s == state);
+    match (pos(cur), pos(to)) {
+        (Some(i), Some(j)) if i < j => FORWARD[i + 1..=j].to_vec(),
+        _ => vec![to],
+    }
+}
+
+const QP_ATTR_NAMES: &[(C::ibv_qp_attr_mask, &str)] = &[
+    (C::IBV_QP_STATE, "qp_state"),
+    (C::IBV_QP_CUR_STATE, "cur_qp_state"),
+    (C::IBV_QP_EN_SQD_ASYNC_NOTIFY, "en_sqd_async_notify"),
+    (C::IBV_QP_ACCESS_FLAGS, "qp_access_flags"),
+    (C::IBV_QP_PKEY_INDEX, "pkey_index"),
+    (C::IBV_QP_PORT, "port_num"),
+    (C::IBV_QP_QKEY, "qkey"),
+    (C::IBV_QP_AV, "ah_attr"),
+    (C::IBV_QP_PATH_MTU, "path_mtu"),
+    (C::IBV_QP_TIMEOUT, "timeout"),
+    (C::IBV_QP_RETRY_CNT, "retry_cnt"),
+    (C::IBV_QP_RNR_RETRY, "rnr_retry"),
+    (C::IBV_QP_RQ_PSN, "rq_psn"),
+    (C::IBV_QP_MAX_QP_RD_ATOMIC, "max_rd_atomic"),
+    (C::IBV_QP_ALT_PATH, "alt_path"),
+    (C::IBV_QP_MIN_RNR_TIMER, "min_rnr_timer"),
+    (C::IBV_QP_SQ_PSN, "sq_psn"),
+    (C::IBV_QP_MAX_DEST_RD_ATOMIC, "max_dest_rd_atomic"),
+    (C::IBV_QP_PATH_MIG_STATE, "path_mig_state"),
+    (C::IBV_QP_CAP, "cap"),
+    (C::IBV_QP_DEST_QPN, "dest_qp_num"),
+    (C::IBV_QP_RATE_LIMIT, "rate_limit"),
+];
+
+fn qp_attr_names(mask: C::ibv_qp_attr_mask) -> String {
+    let names: Vec<&str> = QP_ATTR_NAMES
+        .iter()
+        .filter(|&&(bit, _)| mask & bit != 0)
+        .map(|&(_, name)| name)
+        .collect();
+    names.join(", ")
+}
+
+/// Returns the required and optional attributes of a state transition,
+/// or `None` if the transition is illegal.
+///
+/// The table follows the QP state transition table of the IB spec (section 11.2.4.2).
+/// `ty` is the index of RC, UC and UD.
+fn qp_transition_attrs(
+    cur: c_uint,
+    next: c_uint,
+    ty: usize,
+) -> Option<(C::ibv_qp_attr_mask, C::ibv_qp_attr_mask)> {
+    use C::{IBV_QPS_ERR, IBV_QPS_INIT, IBV_QPS_RESET, IBV_QPS_RTR, IBV_QPS_RTS};
+    use C::{IBV_QPS_SQD, IBV_QPS_SQE};
+
+    const PKEY: C::ibv_qp_attr_mask = C::IBV_QP_PKEY_INDEX;
+    const ACCESS: C::ibv_qp_attr_mask = C::IBV_QP_ACCESS_FLAGS;
+    const CUR: C::ibv_qp_attr_mask = C::IBV_QP_CUR_STATE;
+    const MIG: C::ibv_qp_attr_mask = C::IBV_QP_PATH_MIG_STATE;
+    const ALT: C::ibv_qp_attr_mask = C::IBV_QP_ALT_PATH;
+    // rate limit is an extension of packet pacing devices
+    const RATE: C::ibv_qp_attr_mask = C::IBV_QP_RATE_LIMIT;
+
+    let pick = |rc, uc, ud| [rc, uc, ud][ty];
+
+    let attrs = match (cur, next) {
+        (_, IBV_QPS_RESET | IBV_QPS_ERR) => (0, 0),
+        (IBV_QPS_RESET, IBV_QPS_INIT) => {
+            let req = pick(ACCESS, ACCESS, C::IBV_QP_QKEY) | PKEY | C::IBV_QP_PORT;
+            (req, 0)
+        }
+        (IBV_QPS_INIT, IBV_QPS_INIT) => {
+            let opt = pick(ACCESS, ACCESS, C::IBV_QP_QKEY) | PKEY | C::IBV_QP_PORT;
+            (0, opt)
+        }
+        (IBV_QPS_INIT, IBV_QPS_RTR) => {
+            let path = C::IBV_QP_AV | C::IBV_QP_PATH_MTU | C::IBV_QP_DEST_QPN | C::IBV_QP_RQ_PSN;
+            let rc = path | C::IBV_QP_MAX_DEST_RD_ATOMIC | C::IBV_QP_MIN_RNR_TIMER;
+            let req = pick(rc, path, 0);
+            let opt = pick(
+                ALT | ACCESS | PKEY,
+                ALT | ACCESS | PKEY,
+                PKEY | C::IBV_QP_QKEY,
+            );
+            (req, opt)
+        }
+        (IBV_QPS_RTR, IBV_QPS_RTS) => {
+            let rc = C::IBV_QP_TIMEOUT
+                | C::IBV_QP_RETRY_CNT
+                | C::IBV_QP_RNR_RETRY
+                | C::IBV_QP_MAX_QP_RD_ATOMIC;
+            let req = pick(rc, 0, 0) | C::IBV_QP_SQ_PSN;
+            let uc = CUR | ALT | ACCESS | MIG;
+            let opt = pick(uc | C::IBV_QP_MIN_RNR_TIMER, uc, CUR | C::IBV_QP_QKEY);
+            (req, opt | RATE)
+        }
+        (IBV_QPS_RTS, IBV_QPS_RTS) => {
+            let uc = CUR | ALT | ACCESS | MIG;
+            let opt = pick(uc | C::IBV_QP_MIN_RNR_TIMER, uc, CUR | C::IBV_QP_QKEY);
+            (0, opt | RATE)
+        }
+        (IBV_QPS_SQD, IBV_QPS_RTS) => {
+            let uc = CUR | ALT | ACCESS | MIG;
+            let opt = pick(uc | C::IBV_QP_MIN_RNR_TIMER, uc, CUR | C::IBV_QP_QKEY);
+            (0, opt)
+        }
+        (IBV_QPS_RTS, IBV_QPS_SQD) => (0, C::IBV_QP_EN_SQD_ASYNC_NOTIFY),
+        (IBV_QPS_SQD, IBV_QPS_SQD) => {
+            let uc = C::IBV_QP_AV | ALT | ACCESS | PKEY | MIG;
+            let rc = uc
+                | C::IBV_QP_PORT
+                | C::IBV_QP_TIMEOUT
+                | C::IBV_QP_RETRY_CNT
+                | C::IBV_QP_RNR_RETRY
+                | C::IBV_QP_MAX_QP_RD_ATOMIC
+                | C::IBV_QP_MAX_DEST_RD_ATOMIC
+                | C::IBV_QP_MIN_RNR_TIMER;
+            (0, pick(rc, uc, PKEY | C::IBV_QP_QKEY))
+        }
+        (IBV_QPS_SQE, IBV_QPS_RTS) if ty != 0 => (0, pick(0, CUR | ACCESS, CUR | C::IBV_QP_QKEY)),
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+/// Returns the index of RC, UC and UD in the state transition table
+fn qp_type_index(qp_type: c_uint) -> Option<usize> {
+    match qp_type {
+        C::IBV_QPT_RC => Some(0),
+        C::IBV_QPT_UC => Some(1),
+        C::IBV_QPT_UD => Some(2),
+        _ => None,
+    }
+}
+
+fn state_name(state: c_uint) -> &'static str {
+    match state {
+        C::IBV_QPS_RESET => "RESET",
+        C::IBV_QPS_INIT => "INIT",
+        C::IBV_QPS_RTR => "RTR",
+        C::IBV_QPS_RTS => "RTS",
+        C::IBV_QPS_SQD => "SQD",
+        C::IBV_QPS_SQE => "SQE",
+        C::IBV_QPS_ERR => "ERR",
+        _ => "UNKNOWN",
+    }
+}
+
+fn check_modify_mask(
+    qp_type: c_uint,
+    cur: c_uint,
+    next: c_uint,
+    mask: C::ibv_qp_attr_mask,
+) -> Result<(), String> {
+    let Some(ty) = qp_type_index(qp_type) else {
+        return Ok(());
+    };
+    if cur == C::IBV_QPS_UNKNOWN {
+        return Ok(());
+    }
+    let transition = format!("{} -> {}", state_name(cur), state_name(next));
+
+    let (req, opt) = qp_transition_attrs(cur, next, ty)
+        .ok_or_else(|| format!("illegal queue pair state transition: {transition}"))?;
+
+    let missing = req & !mask;
+    if missing != 0 {
+        let names = qp_attr_names(missing);
+        return Err(format!("missing attributes for {transition}: {names}"));
+    }
+    let illegal = mask & !(req | opt | C::IBV_QP_STATE);
+    if illegal != 0 {
+        let names = qp_attr_names(illegal);
+        return Err(format!("illegal attributes for {transition}: {names}"));
+    }
+    Ok(())
+}
+
+fn check_num_sge(num_sge: c_int, max_sge: u32, name: &str) {
+    let num_sge: u32 = num_sge.numeric_cast();
+    assert!(
+        num_sge <= max_sge,
+        "the request has {num_sge} sges, which exceeds the {name} ({max_sge}) of the queue pair"
+    );
+}
+
+fn checked_psn(psn: u32) -> u32 {
+    debug_assert!(psn <= PSN_MASK, "psn does not fit in 24 bits: {psn:#x}");
+    psn & PSN_MASK
+}
+
+macro_rules! query_option {
+    ($mask: ident, $field: ident) => {
+        #[inline]
+        pub fn $field(&mut self) -> &mut Self {
+            self.mask |= C::$mask;
+            self
+        }
+    };
+}
+
+macro_rules! query_attr {
+    ($mask: ident, $field: ident, $ty: ty) => {
+        #[inline]
+        #[must_use]
+        pub fn $field(&self) -> Option<$ty> {
+            (self.mask & C::$mask != 0).then(|| self.attr.$field)
+        }
+    };
 }
 
 #[derive(Clone, Copy)]
@@ -417,6 +1362,15 @@ impl QueryOptions {
         self.mask |= C::IBV_QP_STATE;
         self
     }
+
+    query_option!(IBV_QP_TIMEOUT, timeout);
+    query_option!(IBV_QP_RETRY_CNT, retry_cnt);
+    query_option!(IBV_QP_RNR_RETRY, rnr_retry);
+    query_option!(IBV_QP_MIN_RNR_TIMER, min_rnr_timer);
+    query_option!(IBV_QP_MAX_QP_RD_ATOMIC, max_rd_atomic);
+    query_option!(IBV_QP_MAX_DEST_RD_ATOMIC, max_dest_rd_atomic);
+    query_option!(IBV_QP_PATH_MIG_STATE, path_mig_state);
+    query_option!(IBV_QP_ALT_PATH, alt_path);
 }
 
 #[repr(C)]
@@ -442,6 +1396,45 @@ impl QueuePairAttr {
     pub fn qp_state(&self) -> Option<QueuePairState> {
         (self.mask & C::IBV_QP_STATE != 0).then(|| QueuePairState::from_c_uint(self.attr.qp_state))
     }
+
+    /// Returns true if the queue pair is in SQD and the send queue is still draining
+    ///
+    /// It is queried along with [`QueryOptions::qp_state`].
+    #[inline]
+    #[must_use]
+    pub fn sq_draining(&self) -> Option<bool> {
+        (self.mask & C::IBV_QP_STATE != 0).then_some(self.attr.sq_draining != 0)
+    }
+
+    query_attr!(IBV_QP_TIMEOUT, timeout, u8);
+    query_attr!(IBV_QP_RETRY_CNT, retry_cnt, u8);
+    query_attr!(IBV_QP_RNR_RETRY, rnr_retry, u8);
+    query_attr!(IBV_QP_MIN_RNR_TIMER, min_rnr_timer, u8);
+    query_attr!(IBV_QP_MAX_QP_RD_ATOMIC, max_rd_atomic, u8);
+    query_attr!(IBV_QP_MAX_DEST_RD_ATOMIC, max_dest_rd_atomic, u8);
+    /// Returns the address vector of the alternate path
+    ///
+    /// It can be passed back to [`ModifyOptions::alt_path`], for example to load
+    /// the same alternate path again after a migration.
+    #[inline]
+    #[must_use]
+    pub fn alt_ah_attr(&self) -> Option<AddressHandleOptions> {
+        (self.mask & C::IBV_QP_ALT_PATH != 0)
+            .then(|| AddressHandleOptions::from_ctype(self.attr.alt_ah_attr))
+    }
+
+    query_attr!(IBV_QP_ALT_PATH, alt_port_num, u8);
+    query_attr!(IBV_QP_ALT_PATH, alt_timeout, u8);
+    query_attr!(IBV_QP_ALT_PATH, alt_pkey_index, u16);
+
+    /// Returns the path migration state, which is `Migrated` after the device
+    /// switches to the alternate path
+    #[inline]
+    #[must_use]
+    pub fn path_mig_state(&self) -> Option<PathMigrationState> {
+        (self.mask & C::IBV_QP_PATH_MIG_STATE != 0)
+            .then(|| PathMigrationState::from_c_uint(self.attr.path_mig_state))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -477,12 +1470,132 @@ impl QueuePairState {
     }
 }
 
+/// The state of automatic path migration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PathMigrationState {
+    /// The alternate path is in use, or no alternate path is loaded
+    Migrated = c_uint_to_u32(C::IBV_MIG_MIGRATED),
+    /// The alternate path is being armed
+    Rearm = c_uint_to_u32(C::IBV_MIG_REARM),
+    /// The device migrates to the alternate path when the primary path fails
+    Armed = c_uint_to_u32(C::IBV_MIG_ARMED),
+}
+
+impl PathMigrationState {
+    fn from_c_uint(val: c_uint) -> Self {
+        match val {
+            C::IBV_MIG_MIGRATED => Self::Migrated,
+            C::IBV_MIG_REARM => Self::Rearm,
+            C::IBV_MIG_ARMED => Self::Armed,
+            _ => panic!("unexpected path migration state"),
+        }
+    }
+
+    fn to_c_uint(self) -> c_uint {
+        #[allow(clippy::as_conversions)]
+        u32_as_c_uint(self as u32)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use rust_utils::offset_of;
 
+    #[test]
+    fn modify_mask() {
+        let rc = C::IBV_QPT_RC;
+        let init = C::IBV_QP_PKEY_INDEX | C::IBV_QP_PORT | C::IBV_QP_ACCESS_FLAGS;
+        let mask = C::IBV_QP_STATE | init;
+        assert!(check_modify_mask(rc, C::IBV_QPS_RESET, C::IBV_QPS_INIT, mask).is_ok());
+
+        let mask = C::IBV_QP_STATE | C::IBV_QP_PKEY_INDEX | C::IBV_QP_PORT;
+        let err = check_modify_mask(rc, C::IBV_QPS_RESET, C::IBV_QPS_INIT, mask).unwrap_err();
+        assert!(
+            err.contains("missing") && err.contains("qp_access_flags"),
+            "{err}"
+        );
+
+        let mask = C::IBV_QP_STATE | init | C::IBV_QP_SQ_PSN;
+        let err = check_modify_mask(rc, C::IBV_QPS_RESET, C::IBV_QPS_INIT, mask).unwrap_err();
+        assert!(err.contains("illegal") && err.contains("sq_psn"), "{err}");
+
+        let ud = C::IBV_QPT_UD;
+        let mask = C::IBV_QP_STATE | C::IBV_QP_SQ_PSN;
+        assert!(check_modify_mask(ud, C::IBV_QPS_RTR, C::IBV_QPS_RTS, mask).is_ok());
+
+        let mask = C::IBV_QP_STATE | C::IBV_QP_RATE_LIMIT;
+        assert!(check_modify_mask(rc, C::IBV_QPS_RTS, C::IBV_QPS_RTS, mask).is_ok());
+        assert!(check_modify_mask(rc, C::IBV_QPS_INIT, C::IBV_QPS_INIT, mask).is_err());
+
+        let mask = C::IBV_QP_STATE;
+        assert!(check_modify_mask(rc, C::IBV_QPS_RESET, C::IBV_QPS_RTS, mask).is_err());
+        assert!(check_modify_mask(rc, C::IBV_QPS_RTS, C::IBV_QPS_ERR, mask).is_ok());
+        assert!(check_modify_mask(rc, C::IBV_QPS_SQE, C::IBV_QPS_RTS, mask).is_err());
+
+        let mask = C::IBV_QP_STATE | C::IBV_QP_EN_SQD_ASYNC_NOTIFY;
+        assert!(check_modify_mask(rc, C::IBV_QPS_RTS, C::IBV_QPS_SQD, mask).is_ok());
+        assert!(check_modify_mask(rc, C::IBV_QPS_RTR, C::IBV_QPS_SQD, mask).is_err());
+        assert!(check_modify_mask(rc, C::IBV_QPS_SQD, C::IBV_QPS_RTS, C::IBV_QP_STATE).is_ok());
+    }
+
+    #[test]
+    fn capacity_limits() {
+        let cap = QueuePairCapacity {
+            max_send_wr: 128,
+            max_recv_wr: 4096,
+            max_send_sge: 1,
+            max_recv_sge: 2,
+            max_inline_data: 64,
+        };
+        assert!(cap.check_limits(4096, 2).is_ok());
+        let err = cap.check_limits(1024, 2).unwrap_err();
+        assert_eq!(err, "max_recv_wr 4096 exceeds the device maximum 1024");
+        let err = cap.check_limits(4096, 1).unwrap_err();
+        assert_eq!(err, "max_recv_sge 2 exceeds the device maximum 1");
+    }
+
+    #[test]
+    fn transition_params() {
+        let (reset, init, rtr, rts) = (
+            C::IBV_QPS_RESET,
+            C::IBV_QPS_INIT,
+            C::IBV_QPS_RTR,
+            C::IBV_QPS_RTS,
+        );
+        assert_eq!(transition_path(reset, rts), [init, rtr, rts]);
+        assert_eq!(transition_path(rtr, rts), [rts]);
+        assert_eq!(transition_path(rts, C::IBV_QPS_SQD), [C::IBV_QPS_SQD]);
+        assert_eq!(transition_path(rts, init), [init]);
+
+        let mut params = TransitionParams::default();
+        params
+            .pkey_index(0)
+            .port_num(1)
+            .qp_access_flags(AccessFlags::REMOTE_WRITE)
+            .sq_psn(0);
+
+        // attributes of later transitions are left out
+        let options = params.transition_options(0, reset, init).unwrap();
+        let init_mask = C::IBV_QP_PKEY_INDEX | C::IBV_QP_PORT | C::IBV_QP_ACCESS_FLAGS;
+        assert_eq!(options.mask, C::IBV_QP_STATE | init_mask);
+
+        let Err(err) = params.transition_options(0, init, rtr) else {
+            panic!()
+        };
+        assert!(
+            err.starts_with("RTR transition requires") && err.contains("dest_qp_num"),
+            "{err}"
+        );
+
+        let Err(err) = params.transition_options(0, reset, rts) else {
+            panic!()
+        };
+        assert!(err.contains("illegal"), "{err}");
+    }
+
     #[test]
     fn qp_cap_layout() {
         assert_eq!(
@@ -515,4 +1628,12 @@ mod tests {
             offset_of!(C::ibv_qp_cap, max_inline_data)
         );
     }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "psn does not fit in 24 bits")]
+    fn psn_overflow() {
+        let mut options = ModifyOptions::default();
+        options.sq_psn(0x0100_0000);
+    }
 }
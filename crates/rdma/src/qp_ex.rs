@@ -0,0 +1,273 @@
+//! Extended work request API
+//!
+//! [`WrBatch`] posts work requests through `ibv_wr_start` ... `ibv_wr_complete`.
+//! All requests between them are written to the send queue directly and
+//! the doorbell is rung once by [`WrBatch::complete`].
+//!
+//! Compared with [`QueuePair::post_send`], which builds a linked list of
+//! `ibv_send_wr` and rings the doorbell for each call, a batch avoids
+//! the intermediate structures and amortizes the doorbell over many requests.
+//! The tradeoff is latency: no request of a batch reaches the device until
+//! the batch is completed, so a large batch delays its first request.
+//! A batch of one request has roughly the same latency as `post_send`.
+
+use crate::ah::AddressHandle;
+use crate::bindings as C;
+use crate::error::from_errno;
+use crate::qp::QueuePair;
+use crate::utils::{c_uint_to_u32, u32_as_c_uint};
+use crate::wr::{SendFlags, Sge};
+
+use std::io;
+use std::marker::PhantomData;
+
+use parking_lot::MutexGuard;
+
+#[allow(clippy::same_name_method)]
+mod flags {
+    use super::*;
+
+    bitflags::bitflags! {
+        /// The operations which can be posted by the extended work request API
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct SendOpsFlags: u32 {
+            const RDMA_WRITE            = c_uint_to_u32(C::IBV_QP_EX_WITH_RDMA_WRITE);
+            const RDMA_WRITE_WITH_IMM   = c_uint_to_u32(C::IBV_QP_EX_WITH_RDMA_WRITE_WITH_IMM);
+            const SEND                  = c_uint_to_u32(C::IBV_QP_EX_WITH_SEND);
+            const SEND_WITH_IMM         = c_uint_to_u32(C::IBV_QP_EX_WITH_SEND_WITH_IMM);
+            const RDMA_READ             = c_uint_to_u32(C::IBV_QP_EX_WITH_RDMA_READ);
+            const ATOMIC_CMP_AND_SWP    = c_uint_to_u32(C::IBV_QP_EX_WITH_ATOMIC_CMP_AND_SWP);
+            const ATOMIC_FETCH_AND_ADD  = c_uint_to_u32(C::IBV_QP_EX_WITH_ATOMIC_FETCH_AND_ADD);
+        }
+    }
+}
+pub use self::flags::*;
+
+/// A batch of work requests which is posted with a single doorbell
+///
+/// The batch is created by [`QueuePair::start_wr`].
+/// Dropping a batch without calling [`WrBatch::complete`] aborts all its requests.
+#[must_use]
+pub struct WrBatch<'a> {
+    qpx: *mut C::ibv_qp_ex,
+    ops: SendOpsFlags,
+    pending: usize,
+    completed: bool,
+    _guard: MutexGuard<'a, ()>,
+    _marker: PhantomData<&'a QueuePair>,
+}
+
+impl<'a> WrBatch<'a> {
+    pub(crate) fn start(
+        qp: &'a QueuePair,
+        ops: SendOpsFlags,
+        guard: MutexGuard<'a, ()>,
+    ) -> io::Result<Self> {
+        // SAFETY: ffi
+        unsafe {
+            let qpx = C::ibv_qp_to_qp_ex(qp.ffi_ptr());
+            if qpx.is_null() || ops.is_empty() {
+                return Err(from_errno(libc::EOPNOTSUPP));
+            }
+            C::ibv_wr_start(qpx);
+            Ok(Self {
+                qpx,
+                ops,
+                pending: 0,
+                completed: false,
+                _guard: guard,
+                _marker: PhantomData,
+            })
+        }
+    }
+
+    /// Returns the number of requests in the batch
+    #[inline]
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    unsafe fn begin(&mut self, op: SendOpsFlags, wr_id: u64, flags: SendFlags) {
+        assert!(
+            self.ops.contains(op),
+            "the queue pair is not created with {op:?}"
+        );
+        (*self.qpx).wr_id = wr_id;
+        (*self.qpx).wr_flags = u32_as_c_uint(flags.bits());
+        self.pending = self.pending.wrapping_add(1);
+    }
+
+    unsafe fn set_sge_list(&mut self, sg_list: &[Sge]) {
+        let sg_list_ptr = sg_list.as_ptr().cast::<C::ibv_sge>();
+        C::ibv_wr_set_sge_list(self.qpx, sg_list.len(), sg_list_ptr);
+    }
+
+    /// Adds a send request
+    ///
+    /// # Safety
+    /// + the buffers of `sg_list` must be valid until the request completes
+    ///
+    /// # Panics
+    /// + if the queue pair is not created with [`SendOpsFlags::SEND`]
+    #[inline]
+    pub unsafe fn send(&mut self, wr_id: u64, flags: SendFlags, sg_list: &[Sge]) -> &mut Self {
+        self.begin(SendOpsFlags::SEND, wr_id, flags);
+        C::ibv_wr_send(self.qpx);
+        self.set_sge_list(sg_list);
+        self
+    }
+
+    /// Adds a send request with immediate data in host byte order
+    ///
+    /// # Safety
+    /// + the buffers of `sg_list` must be valid until the request completes
+    ///
+    /// # Panics
+    /// + if the queue pair is not created with [`SendOpsFlags::SEND_WITH_IMM`]
+    #[inline]
+    pub unsafe fn send_imm(
+        &mut self,
+        wr_id: u64,
+        flags: SendFlags,
+        imm_data: u32,
+        sg_list: &[Sge],
+    ) -> &mut Self {
+        self.begin(SendOpsFlags::SEND_WITH_IMM, wr_id, flags);
+        C::ibv_wr_send_imm(self.qpx, imm_data.to_be());
+        self.set_sge_list(sg_list);
+        self
+    }
+
+    /// Adds an RDMA write request
+    ///
+    /// # Safety
+    /// + the buffers of `sg_list` must be valid until the request completes
+    ///
+    /// # Panics
+    /// + if the queue pair is not created with [`SendOpsFlags::RDMA_WRITE`]
+    #[inline]
+    pub unsafe fn rdma_write(
+        &mut self,
+        wr_id: u64,
+        flags: SendFlags,
+        rkey: u32,
+        remote_addr: u64,
+        sg_list: &[Sge],
+    ) -> &mut Self {
+        self.begin(SendOpsFlags::RDMA_WRITE, wr_id, flags);
+        C::ibv_wr_rdma_write(self.qpx, rkey, remote_addr);
+        self.set_sge_list(sg_list);
+        self
+    }
+
+    /// Adds an RDMA write request with immediate data in host byte order
+    ///
+    /// # Safety
+    /// + the buffers of `sg_list` must be valid until the request completes
+    ///
+    /// # Panics
+    /// + if the queue pair is not created with [`SendOpsFlags::RDMA_WRITE_WITH_IMM`]
+    #[inline]
+    pub unsafe fn rdma_write_imm(
+        &mut self,
+        wr_id: u64,
+        flags: SendFlags,
+        rkey: u32,
+        remote_addr: u64,
+        imm_data: u32,
+        sg_list: &[Sge],
+    ) -> &mut Self {
+        self.begin(SendOpsFlags::RDMA_WRITE_WITH_IMM, wr_id, flags);
+        C::ibv_wr_rdma_write_imm(self.qpx, rkey, remote_addr, imm_data.to_be());
+        self.set_sge_list(sg_list);
+        self
+    }
+
+    /// Adds an RDMA read request
+    ///
+    /// # Safety
+    /// + the buffers of `sg_list` must be valid until the request completes
+    ///
+    /// # Panics
+    /// + if the queue pair is not created with [`SendOpsFlags::RDMA_READ`]
+    #[inline]
+    pub unsafe fn rdma_read(
+        &mut self,
+        wr_id: u64,
+        flags: SendFlags,
+        rkey: u32,
+        remote_addr: u64,
+        sg_list: &[Sge],
+    ) -> &mut Self {
+        self.begin(SendOpsFlags::RDMA_READ, wr_id, flags);
+        C::ibv_wr_rdma_read(self.qpx, rkey, remote_addr);
+        self.set_sge_list(sg_list);
+        self
+    }
+
+    /// Sets the destination of the last request of a UD queue pair
+    ///
+    /// # Safety
+    /// + `ah` must be valid until the request completes
+    ///
+    /// # Panics
+    /// + if the batch is empty
+    #[inline]
+    pub unsafe fn ud_addr(
+        &mut self,
+        ah: &AddressHandle,
+        remote_qpn: u32,
+        remote_qkey: u32,
+    ) -> &mut Self {
+        assert!(self.pending > 0, "the batch is empty");
+        C::ibv_wr_set_ud_addr(self.qpx, ah.ffi_ptr(), remote_qpn, remote_qkey);
+        self
+    }
+
+    /// Sets the DC target of the last request
+    ///
+    /// The batch must belong to a [`DcInitiator`](crate::mlx5::DcInitiator).
+    ///
+    /// # Safety
+    /// + `ah` must be valid until the request completes
+    /// + the queue pair of the batch must be a DC initiator
+    ///
+    /// # Panics
+    /// + if the batch is empty
+    #[cfg(feature = "mlx5")]
+    #[inline]
+    pub unsafe fn dc_addr(
+        &mut self,
+        ah: &AddressHandle,
+        remote_dctn: u32,
+        remote_dc_key: u64,
+    ) -> &mut Self {
+        assert!(self.pending > 0, "the batch is empty");
+        let mqp = C::mlx5dv_qp_ex_from_ibv_qp_ex(self.qpx);
+        C::mlx5dv_wr_set_dc_addr(mqp, ah.ffi_ptr(), remote_dctn, remote_dc_key);
+        self
+    }
+
+    /// Posts all requests of the batch and rings the doorbell once
+    #[inline]
+    pub fn complete(mut self) -> io::Result<()> {
+        self.completed = true;
+        // SAFETY: ffi
+        let ret = unsafe { C::ibv_wr_complete(self.qpx) };
+        if ret != 0 {
+            return Err(from_errno(ret));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WrBatch<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.completed {
+            // SAFETY: ffi
+            unsafe { C::ibv_wr_abort(self.qpx) }
+        }
+    }
+}
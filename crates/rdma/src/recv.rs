@@ -0,0 +1,136 @@
+//! Receive queue helpers
+
+use crate::qp::QueuePair;
+use crate::srq::SharedReceiveQueue;
+use crate::wr::{RecvRequest, Sge};
+
+use std::{io, slice};
+
+use numeric_cast::NumericCast;
+
+/// A queue which receive requests are posted to
+#[derive(Clone)]
+pub enum RecvQueue {
+    QueuePair(QueuePair),
+    Shared(SharedReceiveQueue),
+}
+
+impl RecvQueue {
+    /// Posts a list of receive requests to the underlying queue
+    ///
+    /// # Safety
+    /// The sges of every request must point to registered memory which stays valid
+    /// until the receive is completed or flushed.
+    #[inline]
+    pub unsafe fn post_recv(&self, recv_wr: &RecvRequest) -> io::Result<()> {
+        match self {
+            Self::QueuePair(qp) => qp.post_recv(recv_wr),
+            Self::Shared(srq) => srq.post_recv(recv_wr),
+        }
+    }
+}
+
+impl From<QueuePair> for RecvQueue {
+    #[inline]
+    fn from(qp: QueuePair) -> Self {
+        Self::QueuePair(qp)
+    }
+}
+
+impl From<SharedReceiveQueue> for RecvQueue {
+    #[inline]
+    fn from(srq: SharedReceiveQueue) -> Self {
+        Self::Shared(srq)
+    }
+}
+
+/// Keeps a receive queue filled with a fixed set of buffers
+///
+/// Each buffer is posted with its index as the work request id.
+/// When a receive completes, the buffer is owned by the user until it is
+/// returned by [`RecvReplenisher::on_completion`].
+pub struct RecvReplenisher {
+    queue: RecvQueue,
+    buffers: Vec<Sge>,
+    free: Vec<usize>,
+    low_watermark: usize,
+}
+
+impl RecvReplenisher {
+    /// Creates a replenisher
+    ///
+    /// The depth of the receive queue is the number of buffers.
+    /// [`RecvReplenisher::ensure_filled`] reposts the free buffers when at most
+    /// `low_watermark` receives are outstanding.
+    ///
+    /// # Safety
+    /// The sges must point to registered memory which is valid until the replenisher is dropped
+    /// and all posted receives are completed or flushed.
+    #[inline]
+    #[must_use]
+    pub unsafe fn new(queue: RecvQueue, buffers: Vec<Sge>, low_watermark: usize) -> Self {
+        let free = (0..buffers.len()).rev().collect();
+        Self {
+            queue,
+            buffers,
+            free,
+            low_watermark,
+        }
+    }
+
+    /// Returns the number of outstanding receives
+    #[inline]
+    #[must_use]
+    pub fn posted(&self) -> usize {
+        self.buffers.len() - self.free.len()
+    }
+
+    /// Returns the buffer of a completed receive
+    ///
+    /// # Panics
+    /// + if `wr_id` is not a buffer index
+    #[inline]
+    #[must_use]
+    pub fn buffer(&self, wr_id: u64) -> &Sge {
+        let index: usize = wr_id.numeric_cast();
+        &self.buffers[index]
+    }
+
+    /// Returns the buffer of a completed receive to the replenisher
+    ///
+    /// # Panics
+    /// + if `wr_id` is not a buffer index
+    /// + (debug) if the buffer is not posted
+    #[inline]
+    pub fn on_completion(&mut self, wr_id: u64) {
+        let index: usize = wr_id.numeric_cast();
+        assert!(index < self.buffers.len(), "unknown buffer index: {index}");
+        debug_assert!(
+            !self.free.contains(&index),
+            "the buffer is not posted: {index}"
+        );
+        self.free.push(index);
+    }
+
+    /// Reposts all free buffers if at most `low_watermark` receives are outstanding
+    ///
+    /// Returns the number of posted receives.
+    #[inline]
+    pub fn ensure_filled(&mut self) -> io::Result<usize> {
+        if self.posted() > self.low_watermark {
+            return Ok(0);
+        }
+        let mut cnt = 0;
+        while let Some(&index) = self.free.last() {
+            let mut recv_wr = RecvRequest::zeroed();
+            recv_wr
+                .id(index.numeric_cast())
+                .sg_list(slice::from_ref(&self.buffers[index]));
+            // SAFETY: guaranteed by the caller of `new`
+            unsafe { self.queue.post_recv(&recv_wr)? };
+            self.free.pop();
+            cnt += 1;
+        }
+        Ok(cnt)
+    }
+}
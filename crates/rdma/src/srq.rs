@@ -1,12 +1,13 @@
 use crate::bindings as C;
 use crate::ctx::Context;
-use crate::error::create_resource;
+use crate::error::{check_drop, create_resource, from_errno, get_errno, set_errno};
 use crate::pd::ProtectionDomain;
-use crate::utils::usize_to_void_ptr;
+use crate::utils::{ptr_as_mut, usize_to_void_ptr};
+use crate::wr::RecvRequest;
 
 use std::io;
 use std::mem;
-use std::ptr::NonNull;
+use std::ptr::{self, NonNull};
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -17,6 +18,16 @@ impl SharedReceiveQueue {
         self.0.ffi_ptr()
     }
 
+    /// Returns the underlying `ibv_srq` pointer
+    ///
+    /// The pointer is valid as long as the shared receive queue is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_srq {
+        self.ffi_ptr()
+    }
+
     #[inline]
     #[must_use]
     pub fn options() -> SharedReceiveQueueOptions {
@@ -50,6 +61,28 @@ impl SharedReceiveQueue {
         };
         Ok(Self(owner))
     }
+
+    /// Posts a list of receive requests to the shared receive queue
+    ///
+    /// # Safety
+    /// The sges of every request must point to registered memory which stays valid
+    /// until the receive is completed or flushed.
+    #[inline]
+    pub unsafe fn post_recv(&self, recv_wr: &RecvRequest) -> io::Result<()> {
+        let srq = self.ffi_ptr();
+        let wr: *mut C::ibv_recv_wr = ptr_as_mut(recv_wr).cast();
+        let mut bad_wr: *mut C::ibv_recv_wr = ptr::null_mut();
+        set_errno(0);
+        let ret = C::ibv_post_srq_recv(srq, wr, ptr::addr_of_mut!(bad_wr));
+        if ret != 0 {
+            let errno = get_errno();
+            if errno != 0 {
+                return Err(from_errno(errno));
+            }
+            return Err(from_errno(ret.abs()));
+        }
+        Ok(())
+    }
 }
 
 struct Owner {
@@ -76,7 +109,7 @@ impl Drop for Owner {
         unsafe {
             let srq = self.ffi_ptr();
             let ret = C::ibv_destroy_srq(srq);
-            assert_eq!(ret, 0);
+            check_drop(ret, "ibv_destroy_srq");
         }
     }
 }
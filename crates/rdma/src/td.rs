@@ -0,0 +1,79 @@
+use crate::bindings as C;
+use crate::ctx::Context;
+use crate::error::{check_drop, create_resource};
+
+use std::io;
+use std::ptr::{self, NonNull};
+use std::sync::Arc;
+
+/// A thread domain
+///
+/// Resources in a thread domain are only accessed by one thread at a time,
+/// which allows the driver to skip internal locking.
+/// A thread domain is used through a [`ParentDomain`](crate::pd::ParentDomain).
+#[derive(Clone)]
+pub struct ThreadDomain(Arc<Owner>);
+
+impl ThreadDomain {
+    pub(crate) fn ffi_ptr(&self) -> *mut C::ibv_td {
+        self.0.ffi_ptr()
+    }
+
+    /// Returns the underlying `ibv_td` pointer
+    ///
+    /// The pointer is valid as long as the thread domain is alive.
+    /// The caller must not destroy the resource through the pointer.
+    #[inline]
+    #[must_use]
+    pub fn as_raw(&self) -> *mut C::ibv_td {
+        self.ffi_ptr()
+    }
+
+    /// Allocates a thread domain
+    ///
+    /// Returns `EOPNOTSUPP` if the driver does not support thread domains.
+    #[inline]
+    pub fn alloc(ctx: &Context) -> io::Result<Self> {
+        // SAFETY: ffi
+        let owner = unsafe {
+            let mut init_attr = C::ibv_td_init_attr { comp_mask: 0 };
+            let td = create_resource(
+                || C::ibv_alloc_td(ctx.ffi_ptr(), ptr::addr_of_mut!(init_attr)),
+                || "failed to allocate thread domain",
+            )?;
+            Arc::new(Owner {
+                td,
+                _ctx: ctx.clone(),
+            })
+        };
+        Ok(Self(owner))
+    }
+}
+
+struct Owner {
+    td: NonNull<C::ibv_td>,
+
+    _ctx: Context,
+}
+
+/// SAFETY: owned type
+unsafe impl Send for Owner {}
+/// SAFETY: owned type
+unsafe impl Sync for Owner {}
+
+impl Owner {
+    fn ffi_ptr(&self) -> *mut C::ibv_td {
+        self.td.as_ptr()
+    }
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        // SAFETY: ffi
+        unsafe {
+            let td = self.ffi_ptr();
+            let ret = C::ibv_dealloc_td(td);
+            check_drop(ret, "ibv_dealloc_td");
+        }
+    }
+}
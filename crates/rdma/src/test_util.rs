@@ -0,0 +1,114 @@
+//! Helpers for testing without RDMA hardware
+//!
+//! The helpers run on a software RoCE (RXE) device, which can be created on any
+//! network interface:
+//!
+//! ```text
+//! rdma link add rxe0 type rxe netdev eth0
+//! ```
+//!
+//! Set `RDMA_TEST_DEVICE` to use a specific device instead.
+
+use crate::connect::connect_local;
+use crate::cq::CompletionQueue;
+use crate::ctx::Context;
+use crate::device::{Device, DeviceList, GidEntry, GidType, PortAttr};
+use crate::error::custom_error;
+use crate::pd::ProtectionDomain;
+use crate::qp::{QueuePair, QueuePairCapacity, QueuePairType};
+
+use std::{env, io};
+
+const PORT_NUM: u8 = 1;
+
+/// Returns true if the device is a software RoCE device
+///
+/// RXE devices are detected by the conventional `rxe` name prefix.
+#[inline]
+#[must_use]
+pub fn is_rxe(device: &Device) -> bool {
+    device.name().starts_with("rxe")
+}
+
+/// Returns the device for testing
+///
+/// Uses `RDMA_TEST_DEVICE` if it is set, otherwise the first RXE device.
+#[inline]
+#[must_use]
+pub fn find_test_device(dev_list: &DeviceList) -> Option<&Device> {
+    match env::var("RDMA_TEST_DEVICE") {
+        Ok(name) => dev_list.iter().find(|d| d.name() == name),
+        Err(_) => dev_list.iter().find(|d| is_rxe(d)),
+    }
+}
+
+/// Two RC queue pairs connected to each other over loopback
+pub struct TestPair {
+    pub ctx: Context,
+    pub pd: ProtectionDomain,
+    pub cq: CompletionQueue,
+    pub a: QueuePair,
+    pub b: QueuePair,
+}
+
+impl TestPair {
+    /// Creates two RC queue pairs on the test device and connects them
+    ///
+    /// Both queue pairs share one completion queue and allow remote read and write.
+    #[inline]
+    pub fn new() -> io::Result<Self> {
+        let dev_list = DeviceList::available()?;
+        let dev =
+            find_test_device(&dev_list).ok_or_else(|| custom_error("no test device found"))?;
+        let ctx = dev.open()?;
+
+        let pd = ProtectionDomain::alloc(&ctx)?;
+
+        let cq = {
+            let mut options = CompletionQueue::options();
+            options.cqe(256);
+            CompletionQueue::create(&ctx, options)?
+        };
+
+        let create_qp = || {
+            let cap = QueuePairCapacity {
+                max_send_wr: 64,
+                max_recv_wr: 64,
+                max_send_sge: 1,
+                max_recv_sge: 1,
+                max_inline_data: 0,
+            };
+            let mut options = QueuePair::options();
+            options
+                .send_cq(&cq)
+                .recv_cq(&cq)
+                .cap(cap)
+                .qp_type(QueuePairType::RC)
+                .sq_sig_all(true)
+                .pd(&pd);
+            QueuePair::create(&ctx, options)
+        };
+
+        let a = create_qp()?;
+        let b = create_qp()?;
+
+        let gid_index = find_roce_v2_gid(&ctx)?;
+        connect_local(&a, &b, PORT_NUM, gid_index)?;
+
+        Ok(Self { ctx, pd, cq, a, b })
+    }
+}
+
+fn find_roce_v2_gid(ctx: &Context) -> io::Result<u32> {
+    let port_attr = PortAttr::query(ctx, PORT_NUM)?;
+    for gid_index in 0..port_attr.gid_table_len() {
+        // unused entries can not be queried
+        let Ok(entry) = GidEntry::query(ctx, PORT_NUM.into(), gid_index) else {
+            continue;
+        };
+        if entry.gid_type() == GidType::RoceV2 {
+            return Ok(gid_index);
+        }
+    }
+    Err(custom_error("no RoCEv2 gid found"))
+}
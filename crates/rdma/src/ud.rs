@@ -0,0 +1,36 @@
+//! Helpers for unreliable datagram queue pairs
+
+use crate::device::Mtu;
+
+/// Splits a payload into segments which fit in the path MTU
+///
+/// A UD message can not exceed the path MTU,
+/// so a larger payload must be sent as multiple messages.
+/// An empty payload yields no segments.
+#[inline]
+pub fn segment(payload: &[u8], mtu: Mtu) -> impl Iterator<Item = &[u8]> + '_ {
+    payload.chunks(mtu.size())
+}
+
+/// Returns the number of segments of a payload of `len` bytes
+#[inline]
+#[must_use]
+pub fn segment_count(len: usize, mtu: Mtu) -> usize {
+    len.div_ceil(mtu.size())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_payload() {
+        let payload = vec![0u8; 2500];
+        let lens: Vec<usize> = segment(&payload, Mtu::Mtu1024).map(<[u8]>::len).collect();
+        assert_eq!(lens, [1024, 1024, 452]);
+        assert_eq!(segment_count(payload.len(), Mtu::Mtu1024), 3);
+
+        assert_eq!(segment(&[], Mtu::Mtu256).count(), 0);
+        assert_eq!(segment_count(4096, Mtu::Mtu4096), 1);
+    }
+}
@@ -5,10 +5,24 @@ use std::os::raw::c_uint;
 use std::{fmt, mem};
 
 use numeric_cast::NumericCast;
+use rust_utils::offset_of;
 
 #[repr(transparent)]
 pub struct WorkCompletion(C::ibv_wc);
 
+// the fields read by `WorkCompletion` must match the stable ABI of `ibv_wc`
+const _: () = {
+    assert!(mem::size_of::<C::ibv_wc>() == 48);
+    assert!(mem::align_of::<C::ibv_wc>() == 8);
+    assert!(offset_of!(C::ibv_wc, wr_id) == 0);
+    assert!(offset_of!(C::ibv_wc, status) == 8);
+    assert!(offset_of!(C::ibv_wc, opcode) == 12);
+    assert!(offset_of!(C::ibv_wc, byte_len) == 20);
+    assert!(offset_of!(C::ibv_wc, __bindgen_anon_1) == 24);
+    assert!(offset_of!(C::ibv_wc, qp_num) == 28);
+    assert!(offset_of!(C::ibv_wc, wc_flags) == 36);
+};
+
 impl WorkCompletion {
     #[inline]
     #[must_use]
@@ -28,21 +42,48 @@ impl WorkCompletion {
         self.0.byte_len
     }
 
+    #[inline]
+    #[must_use]
+    pub fn qp_num(&self) -> u32 {
+        self.0.qp_num
+    }
+
     #[inline]
     #[must_use]
     pub fn opcode(&self) -> Opcode {
         Opcode::from_c_uint(self.0.opcode)
     }
 
+    /// Returns the immediate data in host byte order
     #[inline]
     #[must_use]
     pub fn imm_data(&self) -> Option<u32> {
+        self.imm_data_be().map(u32::from_be)
+    }
+
+    /// Returns the immediate data in network byte order
+    #[inline]
+    #[must_use]
+    pub fn imm_data_be(&self) -> Option<u32> {
         // SAFETY: tagged union
         unsafe {
             let has_imm = self.0.wc_flags & C::IBV_WC_WITH_IMM != 0;
             has_imm.then_some(self.0.__bindgen_anon_1.imm_data)
         }
     }
+
+    /// Returns the completion if it is successful, otherwise an error with its `wr_id`
+    #[inline]
+    pub fn check(&self) -> Result<&Self, CompletionError> {
+        match WorkCompletionError::result(self.status()) {
+            Ok(()) => Ok(self),
+            Err(error) => Err(CompletionError {
+                wr_id: self.wr_id(),
+                qp_num: self.qp_num(),
+                error,
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -69,7 +110,7 @@ pub enum Opcode {
 }
 
 impl Opcode {
-    fn from_c_uint(val: c_uint) -> Self {
+    pub(crate) fn from_c_uint(val: c_uint) -> Self {
         match val {
             C::IBV_WC_SEND => Opcode::Send,
             C::IBV_WC_RDMA_WRITE => Opcode::RdmaWrite,
@@ -156,6 +197,67 @@ impl fmt::Display for WorkCompletionError {
 
 impl std::error::Error for WorkCompletionError {}
 
+/// A failed work completion
+///
+/// A failed completion still carries the `wr_id` of its work request,
+/// which is required to release the resources of the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompletionError {
+    wr_id: u64,
+    qp_num: u32,
+    error: WorkCompletionError,
+}
+
+impl CompletionError {
+    #[inline]
+    #[must_use]
+    pub fn wr_id(&self) -> u64 {
+        self.wr_id
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn qp_num(&self) -> u32 {
+        self.qp_num
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn error(&self) -> WorkCompletionError {
+        self.error
+    }
+}
+
+impl fmt::Display for CompletionError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "work request {} of qp {} failed: {}",
+            self.wr_id, self.qp_num, self.error
+        )
+    }
+}
+
+impl std::error::Error for CompletionError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+mod bytemuck_impl {
+    use super::WorkCompletion;
+
+    use bytemuck::Zeroable;
+
+    /// SAFETY: POD
+    ///
+    /// `ibv_wc` contains trailing padding so it can not be `Pod`.
+    unsafe impl Zeroable for WorkCompletion {}
+}
+
 #[cfg(test)]
 mod tests {
     use numeric_cast::NumericCast;
@@ -200,4 +302,17 @@ mod tests {
             numbers.len().numeric_cast::<c_uint>()
         );
     }
+
+    #[test]
+    fn check_keeps_wr_id() {
+        // SAFETY: POD ffi type
+        let mut wc = WorkCompletion(unsafe { mem::zeroed() });
+        wc.0.wr_id = 7;
+        assert!(wc.check().is_ok());
+
+        wc.0.status = C::IBV_WC_WR_FLUSH_ERR;
+        let Err(err) = wc.check() else { panic!() };
+        assert_eq!(err.wr_id(), 7);
+        assert_eq!(err.error(), WorkCompletionError::WRFlush);
+    }
 }
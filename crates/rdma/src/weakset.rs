@@ -22,6 +22,10 @@ impl<T> WeakSet<T> {
         self.set.insert(ptr_to_addr(Weak::into_raw(weak)));
     }
 
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
     pub fn remove(&mut self, p: *const T) -> bool {
         let is_removed = self.set.remove(&ptr_to_addr(p));
         if is_removed {
@@ -43,3 +47,52 @@ impl<T> Drop for WeakSet<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::mem::ManuallyDrop;
+    use std::sync::Arc;
+
+    #[test]
+    fn remove_releases_weak() {
+        let arc = Arc::new(1);
+        let mut set = WeakSet::new();
+        set.insert(Arc::downgrade(&arc));
+        assert_eq!(set.len(), 1);
+        assert_eq!(Arc::weak_count(&arc), 1);
+
+        assert!(set.remove(Arc::as_ptr(&arc)));
+        assert!(!set.remove(Arc::as_ptr(&arc)));
+        assert_eq!(set.len(), 0);
+        assert_eq!(Arc::weak_count(&arc), 0);
+    }
+
+    #[test]
+    fn drop_releases_weak() {
+        let arc = Arc::new(1);
+        let mut set = WeakSet::new();
+        set.insert(Arc::downgrade(&arc));
+        drop(set);
+        assert_eq!(Arc::weak_count(&arc), 0);
+    }
+
+    /// The set keeps the allocation alive after the last strong reference is dropped,
+    /// so an upgrade through the raw pointer fails instead of reading freed memory.
+    #[test]
+    fn upgrade_after_drop() {
+        let arc = Arc::new(1);
+        let p = Arc::as_ptr(&arc);
+        let mut set = WeakSet::new();
+        set.insert(Arc::downgrade(&arc));
+        drop(arc);
+
+        // the same steps as `CompletionQueue::from_cq_context`
+        // SAFETY: the set holds a weak reference of `p`
+        let weak = ManuallyDrop::new(unsafe { Weak::from_raw(p) });
+        assert!(weak.upgrade().is_none());
+
+        assert!(set.remove(p));
+    }
+}
@@ -7,6 +7,14 @@ use std::os::raw::{c_int, c_uint};
 
 use numeric_cast::NumericCast;
 
+/// A send work request
+///
+/// A request can be built once and posted many times.
+/// `ibv_post_send` reads the request and does not modify it,
+/// so only the fields which change need to be updated between posts,
+/// such as the `wr_id` with [`set_id`](Self::set_id).
+/// The sg list and the memory which it points to must stay valid for each post,
+/// and the memory must stay valid until the request completes, unless it is sent inline.
 #[repr(transparent)]
 pub struct SendRequest(C::ibv_send_wr);
 
@@ -27,6 +35,7 @@ unsafe impl Send for RecvRequest {}
 /// the actual usage is unsafe (`C::ibv_post_recv`)
 unsafe impl Sync for RecvRequest {}
 
+#[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Sge {
     pub addr: u64,
@@ -49,12 +58,24 @@ impl SendRequest {
         unsafe { Self(mem::zeroed()) }
     }
 
+    /// Sets the `wr_id` which is returned in the work completion
+    ///
+    /// When several queue pairs share a completion queue, the `wr_id` should also
+    /// identify the owner of the request, such as a connection index in the high bits.
     #[inline]
     pub fn id(&mut self, id: u64) -> &mut Self {
         self.0.wr_id = id;
         self
     }
 
+    /// Updates the `wr_id` of a reused request before it is posted again
+    ///
+    /// The other fields are kept, see [`SendRequest`].
+    #[inline]
+    pub fn set_id(&mut self, id: u64) {
+        self.0.wr_id = id;
+    }
+
     #[inline]
     pub fn next(&mut self, next: *mut Self) -> &mut Self {
         self.0.next = next.cast();
@@ -110,8 +131,20 @@ impl SendRequest {
         self
     }
 
+    /// Sets the immediate data in host byte order
+    ///
+    /// It is used by [`Opcode::SendWithImm`] and [`Opcode::WriteWithImm`].
+    /// The value is converted to network byte order.
+    /// [`WorkCompletion::imm_data`](crate::wc::WorkCompletion::imm_data) on the remote side
+    /// returns the same value in host byte order.
     #[inline]
     pub fn imm_data(&mut self, imm_data: u32) -> &mut Self {
+        self.imm_data_be(imm_data.to_be())
+    }
+
+    /// Sets the immediate data which is already in network byte order
+    #[inline]
+    pub fn imm_data_be(&mut self, imm_data: u32) -> &mut Self {
         self.0.__bindgen_anon_1.imm_data = imm_data;
         self
     }
@@ -125,6 +158,10 @@ impl RecvRequest {
         unsafe { Self(mem::zeroed()) }
     }
 
+    /// Sets the `wr_id` which is returned in the work completion
+    ///
+    /// When several queue pairs share a completion queue, the `wr_id` should also
+    /// identify the owner of the request, such as a connection index in the high bits.
     #[inline]
     pub fn id(&mut self, id: u64) -> &mut Self {
         self.0.wr_id = id;
@@ -151,6 +188,13 @@ pub enum Opcode {
     Send = c_uint_to_u32(C::IBV_WR_SEND),
     SendWithImm = c_uint_to_u32(C::IBV_WR_SEND_WITH_IMM),
     Write = c_uint_to_u32(C::IBV_WR_RDMA_WRITE),
+    /// An RDMA write which also delivers the immediate data to the remote side
+    ///
+    /// The data is written without consuming the remote buffer of a receive request,
+    /// but a receive request must be posted on the remote queue pair to catch
+    /// the completion, whose opcode is [`RecvRdmaWithImm`](crate::wc::Opcode::RecvRdmaWithImm).
+    /// The immediate data is set by [`SendRequest::imm_data`].
+    WriteWithImm = c_uint_to_u32(C::IBV_WR_RDMA_WRITE_WITH_IMM),
     Read = c_uint_to_u32(C::IBV_WR_RDMA_READ),
     AtomicFetchAdd = c_uint_to_u32(C::IBV_WR_ATOMIC_FETCH_AND_ADD),
     AtomicCAS = c_uint_to_u32(C::IBV_WR_ATOMIC_CMP_AND_SWP),
@@ -163,6 +207,61 @@ impl Opcode {
     }
 }
 
+/// Signals every Nth send request of a queue pair created with `sq_sig_all(false)`
+///
+/// ```text
+/// let mut signal = SignalInterval::new(16);
+/// for wr in &mut send_wrs {
+///     let flags = if signal.next() { SendFlags::SIGNALED } else { SendFlags::empty() };
+///     wr.send_flags(flags);
+/// }
+/// // one signaled completion completes `signal.interval()` requests
+/// ```
+#[derive(Debug, Clone)]
+pub struct SignalInterval {
+    interval: u32,
+    unsignaled: u32,
+}
+
+impl SignalInterval {
+    /// # Panics
+    /// + if `interval` is zero
+    #[inline]
+    #[must_use]
+    pub fn new(interval: u32) -> Self {
+        assert!(interval != 0, "the signal interval must be positive");
+        Self {
+            interval,
+            unsignaled: 0,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// Returns the number of requests posted since the last signaled one
+    #[inline]
+    #[must_use]
+    pub fn unsignaled(&self) -> u32 {
+        self.unsignaled
+    }
+
+    /// Returns whether the next request should be signaled
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> bool {
+        self.unsignaled += 1;
+        if self.unsignaled == self.interval {
+            self.unsignaled = 0;
+            return true;
+        }
+        false
+    }
+}
+
 #[allow(clippy::same_name_method)]
 mod flags {
     use super::*;
@@ -170,16 +269,41 @@ mod flags {
     bitflags::bitflags! {
         #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         pub struct SendFlags: u32 {
+            /// The request is not started until all previous RDMA reads and atomics
+            /// of the send queue are complete
+            ///
+            /// This is required when a request depends on the result of a previous read or atomic,
+            /// for example a write which sends the local buffer filled by an outstanding read.
+            /// A read after a write of the same RC queue pair is ordered without a fence.
             const FENCE = c_uint_to_u32(C::IBV_SEND_FENCE);
             const SIGNALED = c_uint_to_u32(C::IBV_SEND_SIGNALED);
             const SOLICITED = c_uint_to_u32(C::IBV_SEND_SOLICITED);
             const INLINE = c_uint_to_u32(C::IBV_SEND_INLINE);
+            /// Offloads the IP and L4 checksum calculation of the packet to the device
+            ///
+            /// It is only valid on UD and raw packet queue pairs.
+            /// Check [`Capabilities::supports_ud_ip_csum`](crate::device::Capabilities::supports_ud_ip_csum)
+            /// and [`Capabilities::supports_raw_ip_csum`](crate::device::Capabilities::supports_raw_ip_csum)
+            /// before using it, because an unsupported device sends the packet with a bad checksum.
             const IP_CSUM = c_uint_to_u32(C::IBV_SEND_IP_CSUM);
         }
     }
 }
 pub use self::flags::*;
 
+#[cfg(feature = "bytemuck")]
+mod bytemuck_impl {
+    use super::Sge;
+
+    use bytemuck::{Pod, Zeroable};
+
+    /// SAFETY: POD
+    unsafe impl Zeroable for Sge {}
+
+    /// SAFETY: POD without padding
+    unsafe impl Pod for Sge {}
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,4 +318,12 @@ mod tests {
         assert_eq!(offset_of!(Sge, length), offset_of!(C::ibv_sge, length));
         assert_eq!(offset_of!(Sge, lkey), offset_of!(C::ibv_sge, lkey));
     }
+
+    #[test]
+    fn signal_interval() {
+        let mut signal = SignalInterval::new(3);
+        let signaled: Vec<bool> = (0..7).map(|_| signal.next()).collect();
+        assert_eq!(signaled, [false, false, true, false, false, true, false]);
+        assert_eq!(signal.unsignaled(), 1);
+    }
 }
@@ -1,5 +1,6 @@
 use crate::driver::RdmaDriver;
 use crate::{LocalAccess, LocalReadAccess, LocalWriteAccess};
+use crate::{RemoteAccess, RemoteReadAccess, RemoteWriteAccess};
 
 use rdma::mr::{AccessFlags, MemoryRegion};
 
@@ -11,6 +12,7 @@ use std::sync::Arc;
 use parking_lot::lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard};
 use parking_lot::{RawRwLock, RwLock};
 use scopeguard::guard;
+use serde::{Deserialize, Serialize};
 
 pub struct Buf {
     pub(crate) mr: ManuallyDrop<MemoryRegion<BufMetadata>>,
@@ -64,6 +66,19 @@ impl Buf {
     pub fn head(self, len: usize) -> Head<Self> {
         Head::new(self, len)
     }
+
+    /// Splits the buffer into two parts at `mid`.
+    ///
+    /// Both parts share the same memory region and can be sent independently.
+    pub fn split_at(self, mid: usize) -> (BufPart, BufPart) {
+        let len = self.mr.length();
+        let whole = BufPart {
+            buf: Arc::new(self),
+            offset: 0,
+            len,
+        };
+        whole.split_at(mid)
+    }
 }
 
 impl Drop for Buf {
@@ -96,6 +111,71 @@ unsafe impl LocalAccess for Buf {
 unsafe impl LocalReadAccess for Buf {}
 unsafe impl LocalWriteAccess for Buf {}
 
+/// A part of a [`Buf`], which is created by [`Buf::split_at`].
+pub struct BufPart {
+    buf: Arc<Buf>,
+    offset: usize,
+    len: usize,
+}
+
+impl BufPart {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn split_at(self, mid: usize) -> (BufPart, BufPart) {
+        assert!(mid <= self.len);
+        let head = BufPart {
+            buf: Arc::clone(&self.buf),
+            offset: self.offset,
+            len: mid,
+        };
+        let tail = BufPart {
+            buf: self.buf,
+            offset: self.offset + mid,
+            len: self.len - mid,
+        };
+        (head, tail)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: the parts of a buf are disjoint, so this does not alias a sibling part
+        unsafe {
+            let base = self.buf.mr.addr_ptr().add(self.offset);
+            slice::from_raw_parts(base, self.len)
+        }
+    }
+
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        // SAFETY: the parts of a buf are disjoint
+        unsafe {
+            let base = self.buf.mr.addr_ptr().add(self.offset);
+            slice::from_raw_parts_mut(base, self.len)
+        }
+    }
+}
+
+unsafe impl LocalAccess for BufPart {
+    fn addr_u64(&self) -> u64 {
+        self.buf.addr_u64() + self.offset as u64
+    }
+
+    fn length(&self) -> usize {
+        self.len
+    }
+
+    fn lkey(&self) -> u32 {
+        self.buf.lkey()
+    }
+}
+
+unsafe impl LocalReadAccess for BufPart {}
+unsafe impl LocalWriteAccess for BufPart {}
+
 pub struct Head<T> {
     inner: T,
     len: usize,
@@ -137,6 +217,51 @@ unsafe impl<T: LocalAccess> LocalAccess for Head<T> {
 unsafe impl<T: LocalReadAccess> LocalReadAccess for Head<T> {}
 unsafe impl<T: LocalWriteAccess> LocalWriteAccess for Head<T> {}
 
+/// A remote buffer which is advertised by the peer.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RemoteBuf {
+    addr: u64,
+    length: usize,
+    rkey: u32,
+}
+
+impl RemoteBuf {
+    pub fn new(addr: u64, length: usize, rkey: u32) -> Self {
+        Self { addr, length, rkey }
+    }
+
+    /// Returns a sub-range of the remote buffer with the same rkey.
+    ///
+    /// # Panics
+    /// + if the range is out of bounds
+    pub fn slice(&self, offset: usize, len: usize) -> RemoteBuf {
+        let end = offset.checked_add(len).expect("range overflow");
+        assert!(end <= self.length, "range out of bounds");
+        Self {
+            addr: self.addr + offset as u64,
+            length: len,
+            rkey: self.rkey,
+        }
+    }
+}
+
+unsafe impl RemoteAccess for RemoteBuf {
+    fn addr_u64(&self) -> u64 {
+        self.addr
+    }
+
+    fn length(&self) -> usize {
+        self.length
+    }
+
+    fn rkey(&self) -> u32 {
+        self.rkey
+    }
+}
+
+unsafe impl RemoteReadAccess for RemoteBuf {}
+unsafe impl RemoteWriteAccess for RemoteBuf {}
+
 #[derive(Clone)]
 pub struct RwBuf(Arc<RwLock<Buf>>);
 
@@ -1,78 +1,334 @@
 use crate::work;
 
+use rdma::bindings as C;
 use rdma::cc::CompChannel;
 use rdma::cq::CompletionQueue;
 use rdma::ctx::Context;
-use rdma::device::DeviceList;
+use rdma::device::DeviceAttr;
+use rdma::event::AsyncEvent;
 use rdma::pd::ProtectionDomain;
 use rdma::wc::WorkCompletion;
 
+use std::collections::HashMap;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::thread;
+use std::{process, thread};
 
-use anyhow::Result;
+use anyhow::{ensure, Context as _, Result};
 use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use tracing::{error, warn};
 
 pub struct RdmaDriver {
     pub(crate) ctx: Context,
     pub(crate) pd: ProtectionDomain,
-    pub(crate) cc: CompChannel,
-    pub(crate) cq: CompletionQueue,
+    pub(crate) ib_port: u8,
+    pub(crate) gid_index: u32,
+    pub(crate) max_sge: u32,
+    shards: Vec<CqShard>,
+    next_shard: AtomicUsize,
+    poll_budget: usize,
+    stats: RwLock<HashMap<u32, Arc<CompletionStats>>>,
+}
+
+/// A completion queue with its own channel and poll thread
+struct CqShard {
+    cc: CompChannel,
+    cq: CompletionQueue,
+}
+
+/// The options of the global driver
+#[derive(Debug, Clone)]
+pub struct DriverOptions {
+    cq_capacity: usize,
+    cq_count: usize,
+    poll_budget: usize,
+    device: Option<String>,
+    ib_port: u8,
+    gid_index: u32,
+    max_sge: u32,
+}
+
+impl Default for DriverOptions {
+    fn default() -> Self {
+        Self {
+            cq_capacity: DEFAULT_CQ_CAPACITY,
+            cq_count: 1,
+            poll_budget: DEFAULT_POLL_BUDGET,
+            device: None,
+            ib_port: DEFAULT_IB_PORT,
+            gid_index: DEFAULT_GID_INDEX,
+            max_sge: 1,
+        }
+    }
+}
+
+impl DriverOptions {
+    /// Sets the capacity of each completion queue
+    pub fn cq_capacity(&mut self, cq_capacity: usize) -> &mut Self {
+        self.cq_capacity = cq_capacity;
+        self
+    }
+
+    /// Sets the number of completion queues
+    ///
+    /// Each completion queue is polled by its own thread and
+    /// the connections are assigned to them in a round-robin way,
+    /// so that an overflow or a slow poll loop only stalls the connections of one queue.
+    /// Setting it to the number of connections gives each connection its own queue.
+    pub fn cq_count(&mut self, cq_count: usize) -> &mut Self {
+        self.cq_count = cq_count;
+        self
+    }
+
+    /// Sets the maximum number of completions which are handled per wakeup
+    ///
+    /// When the budget is used up, the poll thread yields before it continues polling,
+    /// so that a busy completion queue can not monopolize the thread.
+    pub fn poll_budget(&mut self, poll_budget: usize) -> &mut Self {
+        self.poll_budget = poll_budget;
+        self
+    }
+
+    /// Sets the name of the rdma device (default first device found)
+    ///
+    /// The device only carries the data path. The TCP handshake uses the socket addresses
+    /// given to [`RdmaListener::bind`](crate::RdmaListener::bind) and
+    /// [`RdmaConnection::connect_from`](crate::RdmaConnection::connect_from),
+    /// so it can run on a separate management network.
+    pub fn device(&mut self, name: impl Into<String>) -> &mut Self {
+        self.device = Some(name.into());
+        self
+    }
+
+    /// Sets the port of the rdma device
+    pub fn ib_port(&mut self, ib_port: u8) -> &mut Self {
+        self.ib_port = ib_port;
+        self
+    }
+
+    /// Sets the local port gid index
+    pub fn gid_index(&mut self, gid_index: u32) -> &mut Self {
+        self.gid_index = gid_index;
+        self
+    }
+
+    /// Sets the maximum number of buffers of a single send or recv (default 1)
+    ///
+    /// An array of buffers is a scatter or gather list with one sge for each buffer,
+    /// for example a fixed-size header and a payload in separate buffers.
+    /// It must not exceed the `max_sge` of the device, which is checked by
+    /// [`RdmaDriver::init_global`]. Posting more buffers than this in one request
+    /// fails with `InvalidInput`.
+    pub fn max_sge(&mut self, max_sge: u32) -> &mut Self {
+        self.max_sge = max_sge;
+        self
+    }
+}
+
+/// Completion counters of a connection
+#[derive(Debug, Default)]
+pub struct CompletionStats {
+    succeeded: AtomicU64,
+    flushed: AtomicU64,
+    errored: AtomicU64,
+}
+
+impl CompletionStats {
+    /// Returns the number of successful completions
+    pub fn succeeded(&self) -> u64 {
+        self.succeeded.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of requests which are flushed after the queue pair enters the error state
+    pub fn flushed(&self) -> u64 {
+        self.flushed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of completions with other errors
+    pub fn errored(&self) -> u64 {
+        self.errored.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, status: u32) {
+        let counter = match status {
+            C::IBV_WC_SUCCESS => &self.succeeded,
+            C::IBV_WC_WR_FLUSH_ERR => &self.flushed,
+            _ => &self.errored,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 static GLOBAL_DRIVER: OnceCell<Arc<RdmaDriver>> = OnceCell::new();
 
 const DEFAULT_CQ_CAPACITY: usize = 1024;
+const DEFAULT_POLL_BUDGET: usize = 4096;
+const DEFAULT_IB_PORT: u8 = 1;
+const DEFAULT_GID_INDEX: u32 = 2;
 const UNINIT_WC: MaybeUninit<WorkCompletion> = MaybeUninit::uninit();
 
 impl RdmaDriver {
     pub fn global() -> Arc<RdmaDriver> {
-        let driver = GLOBAL_DRIVER.get_or_init(Self::init_default);
+        let driver = GLOBAL_DRIVER.get_or_init(|| {
+            Self::init(&DriverOptions::default()).expect("Failed to initialize rdma driver")
+        });
         Arc::clone(driver)
     }
 
-    fn init_default() -> Arc<RdmaDriver> {
-        let ctx = {
-            let dev_list = DeviceList::available().expect("Failed to get rdma devices");
-            let dev = dev_list.first().expect("No available rdma device");
-            Context::open(dev).expect("Failed to open rdma device")
+    /// Initializes the global driver with `options`
+    ///
+    /// This must be called before any connection or buffer is created,
+    /// otherwise the default options are already in effect and an error is returned.
+    ///
+    /// Returns an error if the device or its resources can not be opened,
+    /// or if `max_sge` exceeds the limit of the device.
+    pub fn init_global(options: &DriverOptions) -> Result<()> {
+        ensure!(options.cq_capacity > 0 && options.cq_count > 0 && options.poll_budget > 0);
+        ensure!(options.max_sge > 0);
+        let mut initialized = false;
+        GLOBAL_DRIVER.get_or_try_init(|| {
+            initialized = true;
+            Self::init(options)
+        })?;
+        ensure!(initialized, "the rdma driver is already initialized");
+        Ok(())
+    }
+
+    fn init(options: &DriverOptions) -> Result<Arc<RdmaDriver>> {
+        let ctx = match options.device {
+            Some(ref name) => Context::open_by_name(name),
+            None => Context::open_first(),
         };
+        let ctx = ctx.context("Failed to open rdma device")?;
 
-        let pd = ProtectionDomain::alloc(&ctx).expect("Failed to allocate protection domain");
+        let device_max_sge = DeviceAttr::query(&ctx)?.max_sge();
+        ensure!(
+            options.max_sge <= device_max_sge,
+            "max_sge {} exceeds the limit of the device ({})",
+            options.max_sge,
+            device_max_sge
+        );
 
-        let cc = CompChannel::create(&ctx).expect("Failed to create completion channel");
+        let pd = ProtectionDomain::alloc(&ctx).context("Failed to allocate protection domain")?;
 
-        let cq = {
-            let mut options = CompletionQueue::options();
-            options.cqe(DEFAULT_CQ_CAPACITY).channel(&cc);
-            CompletionQueue::create(&ctx, options).expect("Failed to create completion queue")
-        };
+        let shards = (0..options.cq_count)
+            .map(|_| {
+                let cc =
+                    CompChannel::create(&ctx).context("Failed to create completion channel")?;
+                let cq = {
+                    let mut cq_options = CompletionQueue::options();
+                    cq_options.cqe(options.cq_capacity).channel(&cc);
+                    CompletionQueue::create(&ctx, cq_options)
+                        .context("Failed to create completion queue")?
+                };
+                Ok(CqShard { cc, cq })
+            })
+            .collect::<Result<_>>()?;
 
-        let driver = Arc::new(RdmaDriver { ctx, pd, cc, cq });
+        let driver = Arc::new(RdmaDriver {
+            ctx,
+            pd,
+            ib_port: options.ib_port,
+            gid_index: options.gid_index,
+            max_sge: options.max_sge,
+            shards,
+            next_shard: AtomicUsize::new(0),
+            poll_budget: options.poll_budget,
+            stats: RwLock::new(HashMap::new()),
+        });
+
+        for index in 0..driver.shards.len() {
+            let driver = Arc::clone(&driver);
+            thread::spawn(move || {
+                driver.run(index).expect("rdma driver failed");
+            });
+        }
 
         {
             let driver = Arc::clone(&driver);
             thread::spawn(move || {
-                driver.run().expect("rdma driver failed");
+                driver.watch_events().expect("rdma driver failed");
             });
         }
 
-        driver
+        Ok(driver)
+    }
+
+    /// Returns the completion queue for a new connection
+    pub(crate) fn assign_cq(&self) -> &CompletionQueue {
+        let index = self.next_shard.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+        &self.shards[index].cq
+    }
+
+    /// Starts counting the completions of a queue pair
+    pub(crate) fn register_stats(&self, qp_num: u32) -> Arc<CompletionStats> {
+        let stats = Arc::new(CompletionStats::default());
+        self.stats.write().insert(qp_num, Arc::clone(&stats));
+        stats
+    }
+
+    /// Stops counting the completions of a queue pair
+    pub(crate) fn unregister_stats(&self, qp_num: u32, stats: &Arc<CompletionStats>) {
+        let mut map = self.stats.write();
+        // the qp number may have been reused by a newer connection
+        if map.get(&qp_num).is_some_and(|s| Arc::ptr_eq(s, stats)) {
+            map.remove(&qp_num);
+        }
+    }
+
+    /// Watches the asynchronous events of the device
+    ///
+    /// A completion queue which overruns stops reporting completions,
+    /// so every pending work on it would hang forever. It is treated as fatal.
+    fn watch_events(self: Arc<Self>) -> Result<()> {
+        loop {
+            let event = AsyncEvent::wait(&self.ctx)?;
+            if self.shards.iter().any(|s| event.is_cq_error_of(&s.cq)) {
+                error!("rdma completion queue overrun, increase DriverOptions::cq_capacity");
+                process::abort();
+            }
+            warn!("rdma async event: {:?}", event.event_type());
+        }
     }
 
-    fn run(self: Arc<Self>) -> Result<()> {
+    fn run(self: Arc<Self>, index: usize) -> Result<()> {
+        let CqShard { cc, cq } = &self.shards[index];
         let mut wc_buf = [UNINIT_WC; DEFAULT_CQ_CAPACITY];
 
+        // whether the last wakeup used up its budget with completions left
+        let mut exhausted = false;
+
         loop {
-            self.cc.wait_cq_event()?;
-            self.cq.ack_cq_events(1);
+            if exhausted {
+                thread::yield_now();
+            } else if cc.wait_cq_event()?.is_some() {
+                cq.ack_cq_events(1);
+            }
+
+            let mut budget = self.poll_budget;
+            exhausted = false;
 
             loop {
-                let wcs = self.cq.poll(&mut wc_buf)?;
+                if budget == 0 {
+                    exhausted = true;
+                    break;
+                }
+                let len = budget.min(wc_buf.len());
+                let wcs = cq.poll(&mut wc_buf[..len])?;
                 if wcs.is_empty() {
                     break;
                 }
+                budget -= wcs.len();
+                {
+                    let stats = self.stats.read();
+                    for wc in wcs.iter() {
+                        if let Some(s) = stats.get(&wc.qp_num()) {
+                            s.record(wc.status());
+                        }
+                    }
+                }
                 for wc in wcs {
                     unsafe { work::complete(wc) };
                 }
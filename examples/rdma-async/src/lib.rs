@@ -9,4 +9,5 @@ mod work;
 
 pub use self::access::*;
 pub use self::buf::*;
-pub use self::net::{RdmaConnection, RdmaListener};
+pub use self::driver::{CompletionStats, DriverOptions, RdmaDriver};
+pub use self::net::{RdmaConnection, RdmaListener, RetryPolicy};
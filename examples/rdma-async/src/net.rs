@@ -1,35 +1,37 @@
-use crate::driver::RdmaDriver;
+use crate::driver::{CompletionStats, RdmaDriver};
 use crate::{work, GatherList, RemoteReadAccess, RemoteWriteAccess, ScatterList};
 
-use rdma::ah::{AddressHandle, GlobalRoute};
+use rdma::ah::AddressHandle;
+use rdma::connect::ConnectionInfo as Dest;
 use rdma::ctx::Context;
-use rdma::device::{Gid, GidEntry, LinkLayer, Mtu, PortAttr};
+use rdma::device::{GidEntry, LinkLayer, PortAttr};
 use rdma::mr::AccessFlags;
 use rdma::qp::{self, QueuePair, QueuePairState};
 
+use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context as _, Result};
+use bincode::Options as _;
 use numeric_cast::NumericCast;
-use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
-
-const DEFAULT_IB_PORT: u8 = 1;
-const DEFAULT_GID_INDEX: u32 = 2;
+use tokio::net::{TcpListener, TcpSocket, TcpStream, ToSocketAddrs};
+use tracing::warn;
 
 fn rc_build_qp(driver: &RdmaDriver) -> io::Result<QueuePair> {
     let ctx = &driver.ctx;
     let pd = &driver.pd;
-    let cq = &driver.cq;
+    let cq = driver.assign_cq();
 
     let cap = qp::QueuePairCapacity {
         max_send_wr: 512,
         max_recv_wr: 512,
-        max_send_sge: 1,
-        max_recv_sge: 1,
-        max_inline_data: 0,
+        max_send_sge: driver.max_sge,
+        max_recv_sge: driver.max_sge,
+        max_inline_data: 64,
     };
 
     let qp = {
@@ -39,7 +41,7 @@ fn rc_build_qp(driver: &RdmaDriver) -> io::Result<QueuePair> {
             .recv_cq(cq)
             .cap(cap)
             .qp_type(qp::QueuePairType::RC)
-            .sq_sig_all(true)
+            .sq_sig_all(false)
             .pd(pd);
 
         QueuePair::create(ctx, options)?
@@ -51,7 +53,7 @@ fn rc_build_qp(driver: &RdmaDriver) -> io::Result<QueuePair> {
         options
             .qp_state(qp::QueuePairState::Initialize)
             .pkey_index(0)
-            .port_num(1)
+            .port_num(driver.ib_port)
             .qp_access_flags(AccessFlags::empty());
 
         qp.modify(options)?;
@@ -73,18 +75,12 @@ fn rc_activate(
         ah_attr.dest_lid(remote_dest.lid).port_num(ib_port);
 
         if remote_dest.gid.interface_id() != 0 {
-            ah_attr.global_route_header(GlobalRoute {
-                dest_gid: remote_dest.gid,
-                flow_label: 0,
-                sgid_index: gid_idx.numeric_cast(),
-                hop_limit: 1,
-                traffic_class: 0,
-            });
+            ah_attr.roce(remote_dest.gid, gid_idx.numeric_cast());
         }
 
         options
             .qp_state(QueuePairState::ReadyToReceive)
-            .path_mtu(Mtu::Mtu1024)
+            .path_mtu(local_dest.mtu.min(remote_dest.mtu))
             .dest_qp_num(remote_dest.qpn)
             .rq_psn(remote_dest.psn)
             .max_dest_rd_atomic(1)
@@ -110,28 +106,23 @@ fn rc_activate(
     Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Dest {
-    qpn: u32,
-    psn: u32,
-    lid: u16,
-    gid: Gid,
+/// The handshake is encoded with fixed-size integers in network byte order,
+/// so that peers with different native endianness agree on the wire format.
+fn dest_codec() -> impl bincode::Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
 }
 
 fn local_dest(ctx: &Context, qp: &QueuePair, ib_port: u8, gid_index: u32) -> Result<Dest> {
-    let qpn = qp.qp_num();
-    let psn = rand::random();
-
     let port_attr = PortAttr::query(ctx, ib_port)?;
-    let lid = port_attr.lid();
-    if port_attr.link_layer() != LinkLayer::Ethernet && lid == 0 {
+    if port_attr.link_layer() != LinkLayer::Ethernet && port_attr.lid() == 0 {
         return Err(anyhow!("Can not get local LID"));
     }
 
     let gid_entry = GidEntry::query(ctx, ib_port.into(), gid_index)?;
-    let gid = gid_entry.gid();
 
-    Ok(Dest { qpn, psn, lid, gid })
+    Ok(Dest::from_local(qp, &port_attr, &gid_entry, gid_index))
 }
 
 async fn exchange_dest(stream: &mut TcpStream, local_dest: &Dest) -> Result<Dest> {
@@ -139,7 +130,7 @@ async fn exchange_dest(stream: &mut TcpStream, local_dest: &Dest) -> Result<Dest
 
     {
         msg_buf.clear();
-        bincode::serialize_into(&mut msg_buf, local_dest)?;
+        dest_codec().serialize_into(&mut msg_buf, local_dest)?;
         let msg_size: u8 = msg_buf.len().numeric_cast();
         stream.write_all(&[msg_size]).await?;
         stream.write_all(&msg_buf).await?;
@@ -152,43 +143,203 @@ async fn exchange_dest(stream: &mut TcpStream, local_dest: &Dest) -> Result<Dest
         msg_buf.clear();
         msg_buf.resize(msg_size[0].into(), 0);
         stream.read_exact(msg_buf.as_mut_slice()).await?;
-        let dest = bincode::deserialize::<Dest>(msg_buf.as_slice())?;
+        let dest = dest_codec().deserialize::<Dest>(msg_buf.as_slice())?;
         Ok(dest)
     }
 }
 
+/// The retry policy of [`RdmaConnection::connect_with_retry`]
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    attempt_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            attempt_timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Sets the maximum number of attempts, including the first one
+    pub fn max_attempts(&mut self, max_attempts: u32) -> &mut Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the delay after the first failed attempt, which doubles after each failure
+    pub fn initial_backoff(&mut self, initial_backoff: Duration) -> &mut Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Sets the upper bound of the delay between attempts
+    pub fn max_backoff(&mut self, max_backoff: Duration) -> &mut Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the timeout of each attempt, see [`RdmaConnection::connect_timeout`]
+    pub fn attempt_timeout(&mut self, attempt_timeout: Duration) -> &mut Self {
+        self.attempt_timeout = Some(attempt_timeout);
+        self
+    }
+}
+
 pub struct RdmaConnection {
     qp: QueuePair,
+    stats: Arc<CompletionStats>,
+    inline_threshold: u32,
 }
 
 impl RdmaConnection {
     pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Self::connect_inner(TcpStream::connect(addr), None).await
+    }
+
+    /// Connects to `addr` with the TCP handshake bound to `local_addr`
+    ///
+    /// The handshake runs on the interface of `local_addr`, which may differ from
+    /// the rdma device selected by [`DriverOptions::device`](crate::DriverOptions::device).
+    pub async fn connect_from(local_addr: SocketAddr, addr: SocketAddr) -> Result<Self> {
+        let stream = async move {
+            let socket = match addr {
+                SocketAddr::V4(_) => TcpSocket::new_v4()?,
+                SocketAddr::V6(_) => TcpSocket::new_v6()?,
+            };
+            socket.bind(local_addr)?;
+            socket.connect(addr).await
+        };
+        Self::connect_inner(stream, None).await
+    }
+
+    /// Connects to `addr`, bounding both the TCP connect and the handshake by `timeout`
+    ///
+    /// On timeout, the partially created queue pair is destroyed.
+    pub async fn connect_timeout<A: ToSocketAddrs>(addr: A, timeout: Duration) -> Result<Self> {
+        Self::connect_inner(TcpStream::connect(addr), Some(timeout)).await
+    }
+
+    /// Connects to `addr`, retrying failed attempts with exponential backoff
+    ///
+    /// Each failed attempt destroys its queue pair before the next one starts.
+    /// Returns the error of the last attempt if all attempts fail.
+    pub async fn connect_with_retry<A>(addr: A, policy: &RetryPolicy) -> Result<Self>
+    where
+        A: ToSocketAddrs + Clone,
+    {
+        let mut backoff = policy.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let stream = TcpStream::connect(addr.clone());
+            match Self::connect_inner(stream, policy.attempt_timeout).await {
+                Ok(conn) => return Ok(conn),
+                Err(err) if attempt >= policy.max_attempts => return Err(err),
+                Err(err) => warn!(attempt, "failed to connect: {err:#}, retry in {backoff:?}"),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2).min(policy.max_backoff);
+            attempt += 1;
+        }
+    }
+
+    async fn connect_inner<F>(stream: F, timeout: Option<Duration>) -> Result<Self>
+    where
+        F: Future<Output = io::Result<TcpStream>>,
+    {
         let driver = RdmaDriver::global();
         let qp = rc_build_qp(&driver)?;
 
-        let local_dest = local_dest(&driver.ctx, &qp, DEFAULT_IB_PORT, DEFAULT_GID_INDEX)?;
-        let mut stream = TcpStream::connect(addr).await?;
-        let remote_dest = exchange_dest(&mut stream, &local_dest).await?;
+        let local_dest = local_dest(&driver.ctx, &qp, driver.ib_port, driver.gid_index)?;
+        let handshake = async {
+            let mut stream = stream.await?;
+            exchange_dest(&mut stream, &local_dest).await
+        };
+        // the qp is dropped with the error, which destroys it
+        let remote_dest = match timeout {
+            None => handshake.await?,
+            Some(timeout) => tokio::time::timeout(timeout, handshake)
+                .await
+                .map_err(|_| anyhow!("rdma connection timed out after {timeout:?}"))??,
+        };
 
         rc_activate(
             &qp,
             &local_dest,
             &remote_dest,
-            DEFAULT_IB_PORT,
-            DEFAULT_GID_INDEX,
+            driver.ib_port,
+            driver.gid_index,
         )?;
 
-        Ok(Self { qp })
+        Ok(Self::new(&driver, qp))
+    }
+
+    fn new(driver: &RdmaDriver, qp: QueuePair) -> Self {
+        let stats = driver.register_stats(qp.qp_num());
+        let inline_threshold = qp.max_inline_data();
+        Self {
+            qp,
+            stats,
+            inline_threshold,
+        }
+    }
+
+    /// Returns the largest payload which is sent inline
+    pub fn inline_threshold(&self) -> u32 {
+        self.inline_threshold
+    }
+
+    /// Sets the largest payload which is sent inline (default `max_inline_data` of the qp)
+    ///
+    /// An inline payload is copied into the work request when it is posted,
+    /// so the device does not read it through the lkey, which reduces the latency of small messages.
+    /// The threshold is capped by `max_inline_data`. Setting it to zero disables inlining.
+    pub fn set_inline_threshold(&mut self, inline_threshold: u32) {
+        self.inline_threshold = inline_threshold.min(self.qp.max_inline_data());
+    }
+
+    /// Returns the completion counters of the connection
+    ///
+    /// After the queue pair enters the error state, the outstanding requests
+    /// are counted as flushed, which shows the blast radius of a failure.
+    pub fn stats(&self) -> &CompletionStats {
+        &self.stats
     }
 
+    /// Sends `slist` as one message, with optional immediate data
+    ///
+    /// `slist` may be an array of buffers, which are concatenated in order.
+    /// The array length must not exceed [`DriverOptions::max_sge`](crate::DriverOptions::max_sge).
     pub async fn send<T>(&self, slist: T, imm: Option<u32>) -> (Result<()>, T)
     where
         T: ScatterList + Send + Sync,
     {
         let qp = self.qp.clone();
-        work::send(qp, slist, imm).await
+        work::send(qp, slist, imm, self.inline_threshold).await
+    }
+
+    pub async fn send_batch<T>(&self, slists: Vec<T>) -> (Result<()>, Vec<T>)
+    where
+        T: ScatterList + Send + Sync,
+    {
+        let qp = self.qp.clone();
+        work::send_batch(qp, slists, self.inline_threshold).await
     }
 
+    /// Receives a message into `glist`, returns the length of the message and the immediate data
+    ///
+    /// `glist` may be an array of buffers, which are filled in order.
+    /// For example, `[header, body]` receives a fixed-size header into its own buffer
+    /// and the rest of the message into `body`.
+    /// The array length must not exceed [`DriverOptions::max_sge`](crate::DriverOptions::max_sge).
     pub async fn recv<T>(&self, glist: T) -> (Result<(usize, Option<u32>)>, T)
     where
         T: GatherList + Send + Sync,
@@ -197,13 +348,56 @@ impl RdmaConnection {
         work::recv(qp, glist).await
     }
 
+    /// Receives the completion of a [`write_with_imm`](Self::write_with_imm) of the peer,
+    /// returns the number of bytes written and the immediate data
+    ///
+    /// The receive takes no buffer, since the data is written into the remote memory.
+    /// Only a `write_with_imm` or a zero-length send of the peer may target it.
+    /// A send with a payload does not fit in the empty receive: it completes with
+    /// a local length error and moves the queue pair to the error state, which breaks the connection.
+    pub async fn recv_imm(&self) -> Result<(usize, Option<u32>)> {
+        let qp = self.qp.clone();
+        work::recv_imm(qp).await
+    }
+
     pub async fn write<T, U>(&self, slist: T, remote: U) -> (Result<()>, (T, U))
     where
         T: ScatterList + Send + Sync,
         U: RemoteWriteAccess + Send + Sync,
     {
         let qp = self.qp.clone();
-        work::write(qp, slist, remote).await
+        work::write(qp, slist, remote, false, None, self.inline_threshold).await
+    }
+
+    /// Writes to the remote memory and delivers `imm` to a receive of the peer
+    ///
+    /// The peer observes the write with [`recv_imm`](Self::recv_imm) or [`recv`](Self::recv),
+    /// which resolves to the number of bytes written and `Some(imm)`.
+    /// The data is visible in the remote memory when the receive completes,
+    /// so the immediate data can signal the write to the peer.
+    pub async fn write_with_imm<T, U>(&self, slist: T, remote: U, imm: u32) -> (Result<()>, (T, U))
+    where
+        T: ScatterList + Send + Sync,
+        U: RemoteWriteAccess + Send + Sync,
+    {
+        let qp = self.qp.clone();
+        let imm = Some(imm);
+        work::write(qp, slist, remote, false, imm, self.inline_threshold).await
+    }
+
+    /// Writes to the remote memory after all previous reads and atomics are complete
+    ///
+    /// Each awaited operation is already complete before the next one is posted.
+    /// Fencing is required only when operations are issued concurrently,
+    /// for example when the local buffer is filled by a previous read,
+    /// or the remote region must be read before it is overwritten.
+    pub async fn write_fenced<T, U>(&self, slist: T, remote: U) -> (Result<()>, (T, U))
+    where
+        T: ScatterList + Send + Sync,
+        U: RemoteWriteAccess + Send + Sync,
+    {
+        let qp = self.qp.clone();
+        work::write(qp, slist, remote, true, None, self.inline_threshold).await
     }
 
     pub async fn read<T, U>(&self, glist: T, remote: U) -> (Result<usize>, (T, U))
@@ -212,7 +406,29 @@ impl RdmaConnection {
         U: RemoteReadAccess + Send + Sync,
     {
         let qp = self.qp.clone();
-        work::read(qp, glist, remote).await
+        work::read(qp, glist, remote, false).await
+    }
+
+    /// Reads from the remote memory after all previous reads and atomics are complete
+    ///
+    /// A read after a write of the same queue pair always observes the write,
+    /// but operations after a read may start before the read is complete.
+    /// Each awaited operation is already complete before the next one is posted.
+    /// Fencing is required only when operations are issued concurrently.
+    pub async fn read_fenced<T, U>(&self, glist: T, remote: U) -> (Result<usize>, (T, U))
+    where
+        T: GatherList + Send + Sync,
+        U: RemoteReadAccess + Send + Sync,
+    {
+        let qp = self.qp.clone();
+        work::read(qp, glist, remote, true).await
+    }
+}
+
+impl Drop for RdmaConnection {
+    fn drop(&mut self) {
+        let driver = RdmaDriver::global();
+        driver.unregister_stats(self.qp.qp_num(), &self.stats);
     }
 }
 
@@ -221,6 +437,9 @@ pub struct RdmaListener {
 }
 
 impl RdmaListener {
+    /// Binds the TCP handshake listener to `addr`
+    ///
+    /// The rdma device is chosen by [`DriverOptions`](crate::DriverOptions), not by `addr`.
     pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
         let tcp = TcpListener::bind(addr).await?;
         Ok(Self { tcp })
@@ -232,17 +451,17 @@ impl RdmaListener {
         let (mut stream, remote_addr) = self.tcp.accept().await?;
         let qp = rc_build_qp(&driver)?;
 
-        let local_dest = local_dest(&driver.ctx, &qp, DEFAULT_IB_PORT, DEFAULT_GID_INDEX)?;
+        let local_dest = local_dest(&driver.ctx, &qp, driver.ib_port, driver.gid_index)?;
         let remote_dest = exchange_dest(&mut stream, &local_dest).await?;
 
         rc_activate(
             &qp,
             &local_dest,
             &remote_dest,
-            DEFAULT_IB_PORT,
-            DEFAULT_GID_INDEX,
+            driver.ib_port,
+            driver.gid_index,
         )?;
 
-        Ok((RdmaConnection { qp }, remote_addr))
+        Ok((RdmaConnection::new(&driver, qp), remote_addr))
     }
 }
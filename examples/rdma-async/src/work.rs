@@ -89,7 +89,18 @@ impl<T: Operation> Work<T> {
     }
 }
 
+/// The id of the unsignaled requests in a send batch.
+///
+/// Only the last request of a batch is signaled and carries the work.
+/// If an unsignaled request fails, its completion is ignored here,
+/// because the queue pair enters the error state and the last request
+/// is flushed with an error, which fails the whole batch.
+const UNSIGNALED_ID: u64 = 0;
+
 pub unsafe fn complete(wc: &WorkCompletion) {
+    if wc.wr_id() == UNSIGNALED_ID {
+        return;
+    }
     let inner: *mut WorkInner<()> = wc.wr_id() as usize as *mut _;
     ((*inner).complete)(wc)
 }
@@ -176,10 +187,26 @@ unsafe fn convert_sglist<R>(sg_list: SgList<'_>, f: impl FnOnce(&[Sge]) -> R) ->
     f(sg_list)
 }
 
+/// Returns `INLINE` if the payload does not exceed `inline_threshold`
+/// and fits in the inline data of the qp.
+///
+/// Otherwise the payload is sent through the sg list.
+fn inline_flag(qp: &QueuePair, sg_list: &[Sge], inline_threshold: u32) -> wr::SendFlags {
+    let len: u64 = sg_list.iter().map(|sge| u64::from(sge.length)).sum();
+    let max_len = inline_threshold.min(qp.max_inline_data());
+    if len > 0 && len <= u64::from(max_len) {
+        wr::SendFlags::INLINE
+    } else {
+        wr::SendFlags::empty()
+    }
+}
+
 unsafe fn submit_single_send(
     qp: &QueuePair,
     id: u64,
     sg_list: SgList<'_>,
+    inline_threshold: u32,
+    fenced: bool,
     f: &mut dyn FnMut(&mut SendRequest),
 ) -> io::Result<()> {
     let cq = qp.send_cq().expect("the qp can not post send");
@@ -187,17 +214,68 @@ unsafe fn submit_single_send(
     cq.req_notify_all()?;
 
     convert_sglist(sg_list, |sg_list| {
+        let mut send_flags = wr::SendFlags::SIGNALED;
+        send_flags |= inline_flag(qp, sg_list, inline_threshold);
+        if fenced {
+            send_flags |= wr::SendFlags::FENCE;
+        }
+
         let mut send_wr = SendRequest::zeroed();
-        send_wr
-            .id(id)
-            .sg_list(sg_list)
-            .send_flags(wr::SendFlags::SIGNALED);
+        send_wr.id(id).sg_list(sg_list).send_flags(send_flags);
         f(&mut send_wr);
 
         qp.post_send(&send_wr)
     })
 }
 
+unsafe fn submit_send_batch<T: ScatterList>(
+    qp: &QueuePair,
+    id: u64,
+    slists: &[T],
+    inline_threshold: u32,
+) -> io::Result<()> {
+    let cq = qp.send_cq().expect("the qp can not post send");
+
+    cq.req_notify_all()?;
+
+    let sg_lists: Vec<Vec<Sge>> = slists
+        .iter()
+        .map(|slist| {
+            let sg_list = SgList::from_slist(slist);
+            let len = sg_list.length();
+            let mut v = Vec::with_capacity(len);
+            sg_list.fill(v.as_mut_ptr());
+            v.set_len(len);
+            v
+        })
+        .collect();
+
+    let mut send_wrs: Vec<SendRequest> = sg_lists
+        .iter()
+        .map(|sg_list| {
+            let mut send_wr = SendRequest::zeroed();
+            send_wr
+                .id(UNSIGNALED_ID)
+                .sg_list(sg_list)
+                .opcode(wr::Opcode::Send)
+                .send_flags(inline_flag(qp, sg_list, inline_threshold));
+            send_wr
+        })
+        .collect();
+
+    if let (Some(last), Some(sg_list)) = (send_wrs.last_mut(), sg_lists.last()) {
+        last.id(id)
+            .send_flags(wr::SendFlags::SIGNALED | inline_flag(qp, sg_list, inline_threshold));
+    }
+
+    let ptr = send_wrs.as_mut_ptr();
+    for i in 1..send_wrs.len() {
+        (*ptr.add(i - 1)).next(ptr.add(i));
+    }
+
+    qp.post_send(&send_wrs[0])
+}
+
 unsafe fn submit_single_recv(qp: &QueuePair, id: u64, sg_list: SgList<'_>) -> io::Result<()> {
     let cq = qp.recv_cq().expect("the qp can not post recv");
 
@@ -228,6 +306,7 @@ fn return_value<F, G>(
 struct OpSend<T> {
     slist: T,
     imm: Option<u32>,
+    inline_threshold: u32,
 }
 
 /// SAFETY: operation type
@@ -240,7 +319,8 @@ where
     fn submit(&mut self, qp: &QueuePair, id: u64) -> io::Result<()> {
         unsafe {
             let sg_list = SgList::from_slist(&self.slist);
-            submit_single_send(qp, id, sg_list, &mut |send_wr| {
+            let inline_threshold = self.inline_threshold;
+            submit_single_send(qp, id, sg_list, inline_threshold, false, &mut |send_wr| {
                 match self.imm {
                     None => send_wr.opcode(wr::Opcode::Send),
                     Some(imm) => send_wr.opcode(wr::Opcode::SendWithImm).imm_data(imm),
@@ -290,11 +370,66 @@ where
     }
 }
 
-pub fn send<T>(qp: QueuePair, slist: T, imm: Option<u32>) -> impl Future<Output = (Result<()>, T)>
+pub fn send<T>(
+    qp: QueuePair,
+    slist: T,
+    imm: Option<u32>,
+    inline_threshold: u32,
+) -> impl Future<Output = (Result<()>, T)>
 where
     T: ScatterList + Send,
 {
-    Work::new(qp, OpSend { slist, imm })
+    let op = OpSend {
+        slist,
+        imm,
+        inline_threshold,
+    };
+    Work::new(qp, op)
+}
+
+struct OpSendBatch<T> {
+    slists: Vec<T>,
+    inline_threshold: u32,
+}
+
+/// SAFETY: operation type
+unsafe impl<T> Operation for OpSendBatch<T>
+where
+    T: ScatterList + Send,
+{
+    type Output = (Result<()>, Vec<T>);
+
+    fn submit(&mut self, qp: &QueuePair, id: u64) -> io::Result<()> {
+        unsafe { submit_send_batch(qp, id, &self.slists, self.inline_threshold) }
+    }
+
+    fn complete(&mut self, _: &WorkCompletion) {}
+
+    fn output(self, result: io::Result<u32>) -> Self::Output {
+        return_value(result, || (), || self.slists)
+    }
+}
+
+/// Sends the messages with only the last request signaled.
+///
+/// The future resolves when the whole batch is complete.
+/// The batch must fit in the send queue.
+pub async fn send_batch<T>(
+    qp: QueuePair,
+    slists: Vec<T>,
+    inline_threshold: u32,
+) -> (Result<()>, Vec<T>)
+where
+    T: ScatterList + Send,
+{
+    if slists.is_empty() {
+        return (Ok(()), slists);
+    }
+    let op = OpSendBatch {
+        slists,
+        inline_threshold,
+    };
+    Work::new(qp, op).await
 }
 
 pub fn recv<T>(qp: QueuePair, glist: T) -> impl Future<Output = (Result<(usize, Option<u32>)>, T)>
@@ -311,9 +446,32 @@ where
     )
 }
 
+/// An empty gather list, for receives which only consume the immediate data of a remote write
+struct NoBuffer;
+
+unsafe impl GatherList for NoBuffer {
+    fn length(&self) -> usize {
+        0
+    }
+
+    unsafe fn fill(&self, _: *mut Sge) {}
+}
+
+/// Receives the completion of a remote write with immediate data.
+///
+/// The data is written into the remote memory of the write, so the receive has no buffer.
+/// The length is the number of bytes written.
+/// A send with a payload fails the receive with `IBV_WC_LOC_LEN_ERR`.
+pub async fn recv_imm(qp: QueuePair) -> Result<(usize, Option<u32>)> {
+    recv(qp, NoBuffer).await.0
+}
+
 pub struct OpWrite<T, U> {
     slist: T,
     remote: U,
+    fenced: bool,
+    imm: Option<u32>,
+    inline_threshold: u32,
 }
 
 /// SAFETY: operation type
@@ -327,12 +485,23 @@ where
     fn submit(&mut self, qp: &QueuePair, id: u64) -> io::Result<()> {
         unsafe {
             let sg_list = SgList::from_slist(&self.slist);
-            submit_single_send(qp, id, sg_list, &mut |send_wr| {
-                send_wr
-                    .opcode(wr::Opcode::Write)
-                    .rdma_remote_addr(self.remote.addr_u64())
-                    .rdma_rkey(self.remote.rkey());
-            })
+            let inline_threshold = self.inline_threshold;
+            submit_single_send(
+                qp,
+                id,
+                sg_list,
+                inline_threshold,
+                self.fenced,
+                &mut |send_wr| {
+                    match self.imm {
+                        None => send_wr.opcode(wr::Opcode::Write),
+                        Some(imm) => send_wr.opcode(wr::Opcode::WriteWithImm).imm_data(imm),
+                    };
+                    send_wr
+                        .rdma_remote_addr(self.remote.addr_u64())
+                        .rdma_rkey(self.remote.rkey());
+                },
+            )
         }
     }
 
@@ -347,6 +516,7 @@ pub struct OpRead<T, U> {
     glist: T,
     remote: U,
     byte_len: u32,
+    fenced: bool,
 }
 
 /// SAFETY: operation type
@@ -360,7 +530,7 @@ where
     fn submit(&mut self, qp: &QueuePair, id: u64) -> io::Result<()> {
         unsafe {
             let sg_list = SgList::from_glist(&self.glist);
-            submit_single_send(qp, id, sg_list, &mut |send_wr| {
+            submit_single_send(qp, id, sg_list, 0, self.fenced, &mut |send_wr| {
                 send_wr
                     .opcode(wr::Opcode::Read)
                     .rdma_remote_addr(self.remote.addr_u64())
@@ -382,18 +552,43 @@ where
     }
 }
 
-pub fn write<T, U>(qp: QueuePair, slist: T, remote: U) -> impl Future<Output = (Result<()>, (T, U))>
+/// Writes to the remote memory.
+///
+/// If `fenced` is true, the write starts after all previous reads and atomics
+/// of the queue pair are complete.
+pub fn write<T, U>(
+    qp: QueuePair,
+    slist: T,
+    remote: U,
+    fenced: bool,
+    imm: Option<u32>,
+    inline_threshold: u32,
+) -> impl Future<Output = (Result<()>, (T, U))>
 where
     T: ScatterList + Send,
     U: RemoteWriteAccess + Send,
 {
-    Work::new(qp, OpWrite { slist, remote })
+    Work::new(
+        qp,
+        OpWrite {
+            slist,
+            remote,
+            fenced,
+            imm,
+            inline_threshold,
+        },
+    )
 }
 
+/// Reads from the remote memory.
+///
+/// If `fenced` is true, the read starts after all previous reads and atomics
+/// of the queue pair are complete.
 pub fn read<T, U>(
     qp: QueuePair,
     glist: T,
     remote: U,
+    fenced: bool,
 ) -> impl Future<Output = (Result<usize>, (T, U))>
 where
     T: GatherList + Send,
@@ -405,6 +600,7 @@ where
             glist,
             remote,
             byte_len: 0,
+            fenced,
         },
     )
 }
@@ -0,0 +1,269 @@
+//! Compares `post_send` with the extended work request API on a loopback RC connection
+
+use rdma::cq::CompletionQueue;
+use rdma::ctx::Context;
+use rdma::device::{PortAttr, PortState};
+use rdma::mr::{AccessFlags, MemoryRegion};
+use rdma::pd::ProtectionDomain;
+use rdma::qp::{QueuePair, QueuePairCapacity, QueuePairType};
+use rdma::qp_ex::SendOpsFlags;
+use rdma::wr::{self, SendFlags, SendRequest, Sge};
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Result};
+use clap::Parser;
+use numeric_cast::NumericCast;
+use tracing::{info, warn};
+
+#[derive(Debug, clap::Parser)]
+struct Args {
+    /// IB device (default first device found)
+    #[clap(short = 'd', long)]
+    ib_dev: Option<String>,
+
+    /// port of IB device
+    #[clap(short = 'i', long, default_value = "1")]
+    ib_port: u8,
+
+    /// local port gid index
+    #[clap(short = 'g', long, default_value = "2")]
+    gid_idx: u32,
+
+    /// size of each rdma write
+    #[clap(short = 's', long, default_value = "64")]
+    size: usize,
+
+    /// number of writes posted with one doorbell
+    #[clap(short = 'b', long, default_value = "16")]
+    batch: usize,
+
+    /// number of batches
+    #[clap(short = 'n', long, default_value = "10000")]
+    iters: usize,
+}
+
+fn main() -> Result<()> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "rdma_bench=info,rdma=info")
+    }
+
+    tracing_subscriber::fmt::init();
+
+    let args = Args::parse();
+
+    info!("args:\n{:#?}", args);
+
+    run(&args)
+}
+
+/// A loopback RC connection: `local` writes into the memory of `remote`
+struct Loopback {
+    cq: CompletionQueue,
+    local: QueuePair,
+    _remote: QueuePair,
+    local_mr: MemoryRegion,
+    remote_mr: MemoryRegion,
+    supports_wr: bool,
+}
+
+fn run(args: &Args) -> Result<()> {
+    ensure!(args.size > 0 && args.batch > 0 && args.iters > 0);
+
+    let ctx = match args.ib_dev.as_deref() {
+        Some(name) => Context::open_by_name(name)?,
+        None => Context::open_first()?,
+    };
+    info!("device name: {}", ctx.device_name());
+
+    let port_attr = PortAttr::query(&ctx, args.ib_port)?;
+    ensure!(
+        port_attr.state() == PortState::Active,
+        "ib port {} is not active ({:?})",
+        args.ib_port,
+        port_attr.state()
+    );
+
+    let pd = ProtectionDomain::alloc(&ctx)?;
+
+    let buf_size = args.size.checked_mul(args.batch).unwrap();
+    let mut local_buf: Vec<u8> = vec![0; buf_size];
+    let mut remote_buf: Vec<u8> = vec![0; buf_size];
+
+    let local_mr = unsafe {
+        let access_flags = AccessFlags::LOCAL_WRITE;
+        MemoryRegion::register(&pd, local_buf.as_mut_ptr(), buf_size, access_flags, ())?
+    };
+    let remote_mr = unsafe {
+        let access_flags = AccessFlags::LOCAL_WRITE | AccessFlags::REMOTE_WRITE;
+        MemoryRegion::register(&pd, remote_buf.as_mut_ptr(), buf_size, access_flags, ())?
+    };
+
+    let cq = {
+        let mut options = CompletionQueue::options();
+        options.cqe(args.batch);
+        CompletionQueue::create(&ctx, options)?
+    };
+
+    let create_qp = |send_ops: SendOpsFlags| {
+        let cap = QueuePairCapacity {
+            max_send_wr: args.batch.numeric_cast(),
+            max_recv_wr: 1,
+            max_send_sge: 1,
+            max_recv_sge: 1,
+            max_inline_data: 0,
+        };
+        let mut options = QueuePair::options();
+        options
+            .send_cq(&cq)
+            .recv_cq(&cq)
+            .cap(cap)
+            .qp_type(QueuePairType::RC)
+            .sq_sig_all(false)
+            .pd(&pd);
+        if !send_ops.is_empty() {
+            options.send_ops_flags(send_ops);
+        }
+        QueuePair::create(&ctx, options)
+    };
+
+    let (local, supports_wr) = match create_qp(SendOpsFlags::RDMA_WRITE) {
+        Ok(qp) => (qp, true),
+        Err(err) => {
+            warn!("the extended work request API is not supported: {err}");
+            (create_qp(SendOpsFlags::empty())?, false)
+        }
+    };
+    let remote = create_qp(SendOpsFlags::empty())?;
+
+    rdma::connect::connect_local(&local, &remote, args.ib_port, args.gid_idx)?;
+
+    let lo = Loopback {
+        cq,
+        local,
+        _remote: remote,
+        local_mr,
+        remote_mr,
+        supports_wr,
+    };
+
+    {
+        let (time_sec, mut latencies) = bench_post_send(&lo, args)?;
+        println!("post_send:");
+        print_statistics(time_sec, &mut latencies, args);
+    }
+
+    if lo.supports_wr {
+        let (time_sec, mut latencies) = bench_wr(&lo, args)?;
+        println!("wr_start/wr_rdma_write/wr_complete:");
+        print_statistics(time_sec, &mut latencies, args);
+    }
+
+    Ok(())
+}
+
+/// Returns the sges of a batch, one chunk of `size` bytes for each write
+fn batch_sges(lo: &Loopback, args: &Args) -> Vec<Sge> {
+    lo.local_mr.chunks(args.size).collect()
+}
+
+fn remote_addr(lo: &Loopback, index: usize, args: &Args) -> u64 {
+    let offset: u64 = (index * args.size).numeric_cast();
+    lo.remote_mr.addr_u64().wrapping_add(offset)
+}
+
+/// The last write of a batch is signaled, so one completion covers the batch
+fn batch_flags(index: usize, args: &Args) -> SendFlags {
+    if index + 1 == args.batch {
+        SendFlags::SIGNALED
+    } else {
+        SendFlags::empty()
+    }
+}
+
+fn wait_completion(cq: &CompletionQueue) -> Result<()> {
+    loop {
+        if let Some(wc) = cq.poll_one()? {
+            wc.check()?;
+            return Ok(());
+        }
+    }
+}
+
+/// Posts each batch as a linked list of `ibv_send_wr` with one `post_send` call
+fn bench_post_send(lo: &Loopback, args: &Args) -> Result<(f64, Vec<Duration>)> {
+    let sges = batch_sges(lo, args);
+    let rkey = lo.remote_mr.rkey();
+
+    let mut wrs: Vec<SendRequest> = (0..args.batch).map(|_| SendRequest::zeroed()).collect();
+    for (i, sge) in sges.iter().enumerate() {
+        wrs[i]
+            .id(i.numeric_cast())
+            .sg_list(std::slice::from_ref(sge))
+            .opcode(wr::Opcode::Write)
+            .send_flags(batch_flags(i, args))
+            .rdma_remote_addr(remote_addr(lo, i, args))
+            .rdma_rkey(rkey);
+    }
+    for i in 1..args.batch {
+        let next: *mut SendRequest = &mut wrs[i];
+        wrs[i - 1].next(next);
+    }
+
+    let mut latencies = Vec::with_capacity(args.iters);
+    let t0 = Instant::now();
+    for _ in 0..args.iters {
+        let t = Instant::now();
+        unsafe { lo.local.post_send(&wrs[0])? };
+        wait_completion(&lo.cq)?;
+        latencies.push(t.elapsed());
+    }
+    Ok((t0.elapsed().as_secs_f64(), latencies))
+}
+
+/// Posts each batch with `ibv_wr_start` ... `ibv_wr_complete`
+fn bench_wr(lo: &Loopback, args: &Args) -> Result<(f64, Vec<Duration>)> {
+    let sges = batch_sges(lo, args);
+    let rkey = lo.remote_mr.rkey();
+
+    let mut latencies = Vec::with_capacity(args.iters);
+    let t0 = Instant::now();
+    for _ in 0..args.iters {
+        let t = Instant::now();
+        let mut batch = lo.local.start_wr()?;
+        for (i, sge) in sges.iter().enumerate() {
+            let flags = batch_flags(i, args);
+            let remote_addr = remote_addr(lo, i, args);
+            let sg_list = std::slice::from_ref(sge);
+            unsafe { batch.rdma_write(i.numeric_cast(), flags, rkey, remote_addr, sg_list) };
+        }
+        batch.complete()?;
+        wait_completion(&lo.cq)?;
+        latencies.push(t.elapsed());
+    }
+    Ok((t0.elapsed().as_secs_f64(), latencies))
+}
+
+fn print_statistics(time_sec: f64, latencies: &mut [Duration], args: &Args) {
+    let ops = (args.iters * args.batch) as f64;
+    let bytes = ops * args.size as f64;
+    println!("  {:.0} ops/sec", ops / time_sec);
+    println!("  {:.2} Mbit/sec", bytes * 8.0 / time_sec / 1e6);
+
+    latencies.sort_unstable();
+    for p in [50.0, 99.0] {
+        let latency = percentile(latencies, p);
+        println!(
+            "  batch latency p{:<3} {:.2} us",
+            p,
+            latency.as_secs_f64() * 1e6
+        );
+    }
+}
+
+/// Returns the `p`-th percentile of `sorted` with the nearest-rank method
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
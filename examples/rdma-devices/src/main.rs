@@ -1,14 +1,41 @@
-use rdma::device::DeviceList;
+use rdma::device::{Device, DeviceAttr, DeviceList, GidEntry, PortAttr};
+use rdma::event::AsyncEvent;
 
-use std::io;
+use std::{io, thread};
 
 fn main() -> io::Result<()> {
+    let has_flag = |short: &str, long: &str| {
+        std::env::args()
+            .skip(1)
+            .any(|arg| arg == short || arg == long)
+    };
+    let verbose = has_flag("-v", "--verbose");
+    let monitor = has_flag("-m", "--monitor");
+
     let dev_list = DeviceList::available()?;
     if dev_list.is_empty() {
         println!("No available rdma devices");
         return Ok(());
     }
 
+    if verbose {
+        for dev in dev_list.as_slice() {
+            print_device_info(dev)?;
+        }
+        return Ok(());
+    }
+
+    if monitor {
+        return thread::scope(|s| {
+            let handles: Vec<_> = dev_list
+                .as_slice()
+                .iter()
+                .map(|dev| s.spawn(|| monitor_ports(dev)))
+                .collect();
+            handles.into_iter().try_for_each(|h| h.join().unwrap())
+        });
+    }
+
     println!("|{:^24}|{:^24}|", "name", "guid");
     println!("|{:-^24}|{:-^24}|", "", "");
 
@@ -20,3 +47,71 @@ fn main() -> io::Result<()> {
 
     Ok(())
 }
+
+fn print_device_info(dev: &Device) -> io::Result<()> {
+    let ctx = dev.open()?;
+    let attr = DeviceAttr::query(&ctx)?;
+    let caps = attr.capabilities();
+
+    println!("device: {}", dev.name());
+    println!("    guid:              {:x}", dev.guid());
+    println!("    max_qp:            {}", attr.max_qp());
+    println!("    max_cq:            {}", attr.max_cq());
+    println!("    max_mr:            {}", attr.max_mr());
+    println!("    max_pd:            {}", attr.max_pd());
+    println!("    max_srq:           {}", attr.max_srq());
+    println!("    max_dm_size:       {}", attr.max_dm_size());
+    println!("    num_comp_vectors:  {}", ctx.num_comp_vectors());
+    println!("    capabilities:      {caps:?}");
+
+    for port_num in 1..=attr.physical_port_count() {
+        let port_attr = PortAttr::query(&ctx, port_num)?;
+        println!("    port: {port_num}");
+        println!("        state:         {:?}", port_attr.state());
+        println!("        link_layer:    {:?}", port_attr.link_layer());
+        println!("        max_mtu:       {}", port_attr.max_mtu().size());
+        println!("        active_mtu:    {}", port_attr.active_mtu().size());
+        println!("        active_speed:  {}", port_attr.active_speed());
+        println!("        active_width:  {}", port_attr.active_width());
+        println!("        lid:           {}", port_attr.lid());
+        println!("        sm_lid:        {}", port_attr.sm_lid());
+
+        for gid_index in 0..port_attr.gid_table_len() {
+            // unused entries can not be queried
+            let Ok(entry) = GidEntry::query(&ctx, port_num.into(), gid_index) else {
+                continue;
+            };
+            println!(
+                "        gid[{gid_index:>3}]:      {:x} ({:?})",
+                entry.gid(),
+                entry.gid_type()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Logs the port transitions of a device until an error occurs
+fn monitor_ports(dev: &Device) -> io::Result<()> {
+    let ctx = dev.open()?;
+    let name = dev.name();
+    println!("monitoring {name}");
+    loop {
+        let event = AsyncEvent::wait(&ctx)?;
+        let Some(port_event) = event.port_event() else {
+            println!("{name}: {:?}", event.event_type());
+            continue;
+        };
+        drop(event);
+
+        let port_num = port_event.port_num();
+        let port_attr = PortAttr::query(&ctx, port_num)?;
+        println!(
+            "{name}: port {port_num}: {port_event:?}, state: {:?}, speed: {}, width: {}",
+            port_attr.state(),
+            port_attr.active_speed(),
+            port_attr.active_width()
+        );
+    }
+}
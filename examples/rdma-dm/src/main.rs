@@ -0,0 +1,163 @@
+//! Writes into on-chip device memory over a loopback RC connection
+
+use rdma::cq::CompletionQueue;
+use rdma::ctx::Context;
+use rdma::device::DeviceAttr;
+use rdma::dm::DeviceMemory;
+use rdma::mr::{AccessFlags, MemoryRegion};
+use rdma::pd::ProtectionDomain;
+use rdma::qp::{QueuePair, QueuePairCapacity, QueuePairType};
+use rdma::wr::{self, SendRequest};
+
+use std::env;
+use std::slice;
+
+use anyhow::{anyhow, ensure, Result};
+use clap::Parser;
+use numeric_cast::NumericCast;
+use tracing::info;
+
+#[derive(Debug, clap::Parser)]
+struct Args {
+    /// IB device (default first device found)
+    #[clap(short = 'd', long)]
+    ib_dev: Option<String>,
+
+    /// port of IB device
+    #[clap(short = 'i', long, default_value = "1")]
+    ib_port: u8,
+
+    /// local port gid index
+    #[clap(short = 'g', long, default_value = "2")]
+    gid_idx: u32,
+
+    /// size of device memory to write
+    #[clap(short = 's', long, default_value = "4096")]
+    size: usize,
+}
+
+const WRITE_WRID: u64 = 1;
+const CHK_SEED: u64 = 0x5eed;
+
+fn main() -> Result<()> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "rdma_dm=info,rdma=info")
+    }
+
+    tracing_subscriber::fmt::init();
+
+    let args = Args::parse();
+
+    info!("args:\n{:#?}", args);
+
+    run(&args)
+}
+
+fn run(args: &Args) -> Result<()> {
+    ensure!(args.size > 0);
+
+    let ctx = match args.ib_dev.as_deref() {
+        Some(name) => Context::open_by_name(name)?,
+        None => Context::open_first()?,
+    };
+    info!("device name: {}", ctx.device_name());
+
+    {
+        let max_dm_size = DeviceAttr::query(&ctx)?.max_dm_size();
+        info!(?max_dm_size);
+        ensure!(
+            args.size <= max_dm_size.numeric_cast(),
+            "device memory is not supported or smaller than {} bytes",
+            args.size
+        );
+    }
+
+    let pd = ProtectionDomain::alloc(&ctx)?;
+
+    // the target: on-chip memory, registered zero-based
+    let dm = {
+        let mut options = DeviceMemory::options();
+        options.length(args.size);
+        DeviceMemory::alloc(&ctx, options)?
+    };
+    dm.copy_from_slice(0, &vec![0; args.size])?;
+
+    let dm_mr = {
+        let access_flags = AccessFlags::LOCAL_WRITE | AccessFlags::REMOTE_WRITE;
+        MemoryRegion::register_dm(&pd, &dm, 0, args.size, access_flags, ())?
+    };
+
+    // the source: host memory filled with a pattern
+    let mut src_buf = vec![0; args.size];
+    rdma::check::fill_pattern(&mut src_buf, CHK_SEED);
+
+    let src_mr = unsafe {
+        let access_flags = AccessFlags::LOCAL_WRITE;
+        MemoryRegion::register(&pd, src_buf.as_mut_ptr(), args.size, access_flags, ())?
+    };
+
+    let cq = {
+        let mut options = CompletionQueue::options();
+        options.cqe(2);
+        CompletionQueue::create(&ctx, options)?
+    };
+
+    let create_qp = || {
+        let cap = QueuePairCapacity {
+            max_send_wr: 1,
+            max_recv_wr: 1,
+            max_send_sge: 1,
+            max_recv_sge: 1,
+            max_inline_data: 0,
+        };
+        let mut options = QueuePair::options();
+        options
+            .send_cq(&cq)
+            .recv_cq(&cq)
+            .cap(cap)
+            .qp_type(QueuePairType::RC)
+            .sq_sig_all(true)
+            .pd(&pd);
+        QueuePair::create(&ctx, options)
+    };
+
+    let writer = create_qp()?;
+    let target = create_qp()?;
+    rdma::connect::connect_local(&writer, &target, args.ib_port, args.gid_idx)?;
+
+    // the peer only needs the rkey and the length, since the region is zero-based
+    let (remote_addr, rkey) = (dm_mr.iova(), dm_mr.rkey());
+    info!(remote_addr, rkey, "advertise device memory region");
+
+    {
+        let sge = src_mr.sge();
+        let mut wr = SendRequest::zeroed();
+        wr.id(WRITE_WRID)
+            .sg_list(slice::from_ref(&sge))
+            .opcode(wr::Opcode::Write)
+            .send_flags(wr::SendFlags::SIGNALED)
+            .rdma_remote_addr(remote_addr)
+            .rdma_rkey(rkey);
+        unsafe { writer.post_send(&wr)? };
+    }
+
+    {
+        let wc = loop {
+            if let Some(wc) = cq.poll_one()? {
+                break wc;
+            }
+        };
+        ensure!(wc.wr_id() == WRITE_WRID);
+        wc.check()?;
+    }
+
+    let mut dst_buf = vec![0; args.size];
+    dm.copy_to_slice(0, &mut dst_buf)?;
+    if let Err(offset) = rdma::check::verify_pattern(&dst_buf, CHK_SEED) {
+        return Err(anyhow!("corrupted data at offset {}", offset));
+    }
+
+    println!("wrote and verified {} bytes of device memory", args.size);
+
+    Ok(())
+}
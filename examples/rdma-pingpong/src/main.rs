@@ -1,28 +1,28 @@
 #![deny(clippy::all)]
 
-use rdma::ah::{AddressHandle, GlobalRoute};
+use rdma::ah::AddressHandle;
 use rdma::cc::CompChannel;
-use rdma::cq::CompletionQueue;
+use rdma::connect::ConnectionInfo as Dest;
+use rdma::cq::{CompletionBuffer, CompletionQueue};
 use rdma::ctx::Context;
-use rdma::device::{Device, DeviceList, Gid, GidEntry, LinkLayer, Mtu, PortAttr, PortState};
+use rdma::device::{GidEntry, LinkLayer, PortAttr, PortState};
+use rdma::grh::GRH_SIZE;
 use rdma::mr::{AccessFlags, MemoryRegion};
 use rdma::pd::ProtectionDomain;
 use rdma::qp::{self, QueuePair};
 use rdma::qp::{QueuePairCapacity, QueuePairState, QueuePairType};
-use rdma::wc::{WorkCompletion, WorkCompletionError};
 use rdma::wr;
 
 use std::env;
 use std::io::{Read, Write};
-use std::mem::MaybeUninit;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
 use std::slice;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, ensure, Context as _, Result};
+use bincode::Options as _;
 use clap::Parser;
 use numeric_cast::NumericCast;
-use serde::{Deserialize, Serialize};
 use tracing::{info, trace};
 
 #[derive(Debug, clap::Parser)]
@@ -47,6 +47,10 @@ struct Args {
     #[clap(short = 'g', long, default_value = "2")]
     gid_idx: u32,
 
+    /// service level value
+    #[clap(short = 'l', long, default_value = "0")]
+    sl: u8,
+
     /// listen on/connect to port
     #[clap(short = 'p', long, default_value = "18515")]
     port: u16,
@@ -55,6 +59,18 @@ struct Args {
     #[clap(short = 'n', long, default_value = "1000")]
     iters: usize,
 
+    /// sleep on CQ events (default poll)
+    #[clap(short = 'e', long)]
+    events: bool,
+
+    /// validate received buffer
+    #[clap(short = 'c', long)]
+    chk: bool,
+
+    /// print latency percentiles
+    #[clap(long)]
+    histogram: bool,
+
     #[clap(value_parser = parse_qp_type)]
     qp_type: QueuePairType,
 
@@ -91,38 +107,46 @@ fn main() -> Result<()> {
     run(args)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Dest {
-    qpn: u32,
-    psn: u32,
-    lid: u16,
-    gid: Gid,
+/// The handshake is encoded with fixed-size integers in network byte order,
+/// so that peers with different native endianness agree on the wire format.
+fn dest_codec() -> impl bincode::Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
 }
 
 const RECV_WRID: u64 = 1;
 const SEND_WRID: u64 = 2;
 const UD_QKEY: u32 = 0x11111111;
-
-const UNINIT_WC: MaybeUninit<WorkCompletion> = MaybeUninit::uninit();
+const CHK_SEED: u64 = 0x5eed;
 
 fn run(args: Args) -> Result<()> {
     ensure!(args.size > 0);
 
     let buf_size = match args.qp_type {
         QueuePairType::RC => args.size,
-        QueuePairType::UD => args.size.checked_add(40).unwrap(),
+        QueuePairType::UD => args.size.checked_add(GRH_SIZE).unwrap(),
+        _ => unimplemented!(),
+    };
+
+    let payload = match args.qp_type {
+        QueuePairType::RC => 0..args.size,
+        QueuePairType::UD => GRH_SIZE..buf_size,
         _ => unimplemented!(),
     };
 
     let mut send_buf: Vec<u8> = vec![0; buf_size];
     let mut recv_buf: Vec<u8> = vec![0; buf_size];
 
-    let ctx = {
-        let dev_list = DeviceList::available()?;
-        let dev = choose_device(&dev_list, args.ib_dev.as_deref())?;
-        info!("device name: {}", dev.name());
-        Context::open(dev)?
+    if args.chk {
+        rdma::check::fill_pattern(&mut send_buf[payload.clone()], CHK_SEED);
+    }
+
+    let ctx = match args.ib_dev.as_deref() {
+        Some(name) => Context::open_by_name(name)?,
+        None => Context::open_first()?,
     };
+    info!("device name: {}", ctx.device_name());
 
     {
         let port_attr = PortAttr::query(&ctx, args.ib_port)?;
@@ -217,11 +241,7 @@ fn run(args: Args) -> Result<()> {
 
     let recv_sge;
     let recv_wr = {
-        recv_sge = wr::Sge {
-            addr: recv_mr.addr_u64(),
-            length: recv_mr.length().numeric_cast(),
-            lkey: recv_mr.lkey(),
-        };
+        recv_sge = recv_mr.sge();
 
         let mut wr = wr::RecvRequest::zeroed();
         wr.id(RECV_WRID).sg_list(slice::from_ref(&recv_sge));
@@ -230,14 +250,10 @@ fn run(args: Args) -> Result<()> {
 
     let send_sge;
     let send_wr = {
-        send_sge = wr::Sge {
-            addr: match args.qp_type {
-                QueuePairType::RC => send_mr.addr_u64(),
-                QueuePairType::UD => send_mr.addr_u64().wrapping_add(40),
-                _ => unimplemented!(),
-            },
-            length: args.size.numeric_cast(),
-            lkey: send_mr.lkey(),
+        send_sge = match args.qp_type {
+            QueuePairType::RC => send_mr.sge_range(0, args.size),
+            QueuePairType::UD => send_mr.sge_range(GRH_SIZE, args.size),
+            _ => unimplemented!(),
         };
 
         let mut wr = wr::SendRequest::zeroed();
@@ -259,20 +275,26 @@ fn run(args: Args) -> Result<()> {
         wr
     };
 
-    let time_sec = {
+    let (time_sec, mut latencies) = {
         let mut recv_comp_cnt = 0;
         let mut send_comp_cnt = 0;
         let mut recv_req_cnt = 0;
         let mut send_req_cnt = 0;
 
-        let mut wc_buf = [UNINIT_WC; 2];
+        let mut wc_buf = CompletionBuffer::new(2);
+
+        let mut latencies = Vec::with_capacity(if args.histogram { args.iters } else { 0 });
+        let mut iter_cnt = 0;
 
         info!("start iteration");
 
         let t0 = Instant::now();
+        let mut last = t0;
 
         loop {
-            cq.req_notify_all()?;
+            if args.events {
+                cq.req_notify_all()?;
+            }
 
             loop {
                 if recv_req_cnt <= 1 {
@@ -290,10 +312,10 @@ fn run(args: Args) -> Result<()> {
 
                 trace!("poll cq");
 
-                let wcs = cq.poll(&mut wc_buf)?;
+                let wcs = wc_buf.poll(&cq)?;
 
                 for wc in &mut *wcs {
-                    WorkCompletionError::result(wc.status())?;
+                    wc.check()?;
 
                     match wc.wr_id() {
                         SEND_WRID => {
@@ -301,6 +323,12 @@ fn run(args: Args) -> Result<()> {
                             send_req_cnt -= 1;
                         }
                         RECV_WRID => {
+                            if args.chk {
+                                let data = &recv_buf[payload.clone()];
+                                if let Err(offset) = rdma::check::verify_pattern(data, CHK_SEED) {
+                                    return Err(anyhow!("corrupted data at offset {}", offset));
+                                }
+                            }
                             recv_comp_cnt += 1;
                             recv_req_cnt -= 1;
                         }
@@ -308,6 +336,17 @@ fn run(args: Args) -> Result<()> {
                     }
                 }
 
+                if args.histogram {
+                    let done = send_comp_cnt.min(recv_comp_cnt).min(args.iters);
+                    if done > iter_cnt {
+                        let now = Instant::now();
+                        let n: u32 = (done - iter_cnt).numeric_cast();
+                        latencies.extend((0..n).map(|_| (now - last) / n));
+                        last = now;
+                        iter_cnt = done;
+                    }
+                }
+
                 if wcs.is_empty() {
                     break;
                 }
@@ -319,15 +358,16 @@ fn run(args: Args) -> Result<()> {
                 break;
             }
 
-            cc.wait_cq_event()?;
-            cq.ack_cq_events(1);
+            if args.events && cc.wait_cq_event()?.is_some() {
+                cq.ack_cq_events(1);
+            }
         }
 
         let t1 = Instant::now();
 
         info!("end iteration");
 
-        (t1 - t0).as_secs_f64()
+        ((t1 - t0).as_secs_f64(), latencies)
     };
 
     {
@@ -335,21 +375,11 @@ fn run(args: Args) -> Result<()> {
         print_statistics(time_sec, bytes, args.iters)
     }
 
-    Ok(())
-}
-
-fn choose_device<'dl>(dev_list: &'dl DeviceList, name: Option<&str>) -> Result<&'dl Device> {
-    let dev = match name {
-        Some(name) => dev_list.iter().find(|d| d.name() == name),
-        None => dev_list.first(),
-    };
-    if let Some(dev) = dev {
-        return Ok(dev);
+    if args.histogram {
+        print_latency_percentiles(&mut latencies);
     }
-    if dev_list.is_empty() {
-        return Err(anyhow!("No available rdma devices"));
-    }
-    Err(anyhow!("Can not find device with name: {}", name.unwrap()))
+
+    Ok(())
 }
 
 fn initialize(qp: &QueuePair, args: &Args) -> Result<()> {
@@ -371,19 +401,14 @@ fn initialize(qp: &QueuePair, args: &Args) -> Result<()> {
 }
 
 fn local_dest(ctx: &Context, qp: &QueuePair, ib_port: u8, gid_index: u32) -> Result<Dest> {
-    let qpn = qp.qp_num();
-    let psn = 0x123456;
-
     let port_attr = PortAttr::query(ctx, ib_port)?;
-    let lid = port_attr.lid();
-    if port_attr.link_layer() != LinkLayer::Ethernet && lid == 0 {
+    if port_attr.link_layer() != LinkLayer::Ethernet && port_attr.lid() == 0 {
         return Err(anyhow!("Can not get local LID"));
     }
 
     let gid_entry = GidEntry::query(ctx, ib_port.into(), gid_index)?;
-    let gid = gid_entry.gid();
 
-    Ok(Dest { qpn, psn, lid, gid })
+    Ok(Dest::from_local(qp, &port_attr, &gid_entry, gid_index))
 }
 
 fn exchange_dest_over_tcp(server: Option<IpAddr>, port: u16, local_dest: &Dest) -> Result<Dest> {
@@ -407,7 +432,7 @@ fn exchange_dest_over_tcp(server: Option<IpAddr>, port: u16, local_dest: &Dest)
 
     let send_dest = |stream: &mut TcpStream, msg_buf: &mut Vec<u8>, dest: &Dest| {
         msg_buf.clear();
-        bincode::serialize_into(&mut *msg_buf, &dest)?;
+        dest_codec().serialize_into(&mut *msg_buf, &dest)?;
         let msg_size: u8 = msg_buf.len().numeric_cast();
         stream.write_all(&[msg_size])?;
         stream.write_all(msg_buf)?;
@@ -421,7 +446,7 @@ fn exchange_dest_over_tcp(server: Option<IpAddr>, port: u16, local_dest: &Dest)
         msg_buf.clear();
         msg_buf.resize(msg_size[0].into(), 0);
         stream.read_exact(&mut *msg_buf)?;
-        let dest = bincode::deserialize::<Dest>(&*msg_buf)?;
+        let dest = dest_codec().deserialize::<Dest>(&*msg_buf)?;
         anyhow::Result::<Dest>::Ok(dest)
     };
 
@@ -436,21 +461,18 @@ fn rc_activate(qp: &QueuePair, local_dest: &Dest, remote_dest: &Dest, args: &Arg
         let mut options = qp::ModifyOptions::default();
 
         let mut ah_attr = AddressHandle::options();
-        ah_attr.dest_lid(remote_dest.lid).port_num(args.ib_port);
+        ah_attr
+            .dest_lid(remote_dest.lid)
+            .service_level(args.sl)
+            .port_num(args.ib_port);
 
         if remote_dest.gid.interface_id() != 0 {
-            ah_attr.global_route_header(GlobalRoute {
-                dest_gid: remote_dest.gid,
-                flow_label: 0,
-                sgid_index: args.gid_idx.numeric_cast(),
-                hop_limit: 1,
-                traffic_class: 0,
-            });
+            ah_attr.roce(remote_dest.gid, args.gid_idx.numeric_cast());
         }
 
         options
             .qp_state(QueuePairState::ReadyToReceive)
-            .path_mtu(Mtu::Mtu1024)
+            .path_mtu(local_dest.mtu.min(remote_dest.mtu))
             .dest_qp_num(remote_dest.qpn)
             .rq_psn(remote_dest.psn)
             .max_dest_rd_atomic(1)
@@ -501,16 +523,13 @@ fn ud_activate(
 
     {
         let mut options = AddressHandle::options();
-        options.dest_lid(remote_dest.lid).port_num(args.ib_port);
+        options
+            .dest_lid(remote_dest.lid)
+            .service_level(args.sl)
+            .port_num(args.ib_port);
 
         if remote_dest.gid.interface_id() != 0 {
-            options.global_route_header(GlobalRoute {
-                dest_gid: remote_dest.gid,
-                flow_label: 0,
-                sgid_index: args.gid_idx.numeric_cast(),
-                hop_limit: 1,
-                traffic_class: 0,
-            });
+            options.roce(remote_dest.gid, args.gid_idx.numeric_cast());
         }
 
         let ah = AddressHandle::create(pd, options)?;
@@ -532,3 +551,21 @@ fn print_statistics(time_sec: f64, bytes: usize, iters: usize) {
         time_sec * 1e6 / (iters as f64)
     );
 }
+
+fn print_latency_percentiles(latencies: &mut [Duration]) {
+    if latencies.is_empty() {
+        return;
+    }
+    latencies.sort_unstable();
+    let percentile = |p: f64| {
+        let rank = (p / 100.0 * latencies.len() as f64).ceil() as usize;
+        latencies[rank.clamp(1, latencies.len()) - 1]
+    };
+    for p in [50.0, 90.0, 99.0, 99.9] {
+        println!("p{:<5} {:.2} us", p, percentile(p).as_secs_f64() * 1e6);
+    }
+    println!(
+        "max    {:.2} us",
+        latencies[latencies.len() - 1].as_secs_f64() * 1e6
+    );
+}
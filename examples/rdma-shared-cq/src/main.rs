@@ -0,0 +1,270 @@
+//! Serves several loopback RC connections with one completion queue
+//!
+//! All queue pairs share one CQ, so a completion must be routed back to its connection.
+//! A completion carries two keys:
+//!
+//! + `qp_num`: the queue pair of the completion, which is unique in the device
+//! + `wr_id`: an opaque `u64` chosen by the poster of the request
+//!
+//! `qp_num` only identifies the queue pair, not the request. This example encodes
+//! the connection index in the high 32 bits of `wr_id` and the sequence number of the
+//! request in the low 32 bits, so each `wr_id` is unique across all queue pairs.
+//! Using the same `wr_id` (such as a per-connection counter) on every queue pair
+//! makes completions of different connections indistinguishable by `wr_id`.
+//!
+//! Another convention is to store a pointer in `wr_id`: the async example puts the
+//! `Arc` of the pending operation into `wr_id` and recovers it with `Arc::from_raw`.
+//!
+//! Every completion is validated twice: the connection decoded from `wr_id` must own
+//! the queue pair which is reported by `qp_num`.
+
+use rdma::cq::{CompletionBuffer, CompletionQueue};
+use rdma::ctx::Context;
+use rdma::mr::{AccessFlags, MemoryRegion};
+use rdma::pd::ProtectionDomain;
+use rdma::qp::{QueuePair, QueuePairCapacity, QueuePairType};
+use rdma::wc;
+use rdma::wr::{self, RecvRequest, SendRequest};
+
+use std::collections::HashMap;
+use std::env;
+use std::slice;
+
+use anyhow::{anyhow, ensure, Result};
+use clap::Parser;
+use numeric_cast::NumericCast;
+use tracing::{debug, info};
+
+#[derive(Debug, clap::Parser)]
+struct Args {
+    /// IB device (default first device found)
+    #[clap(short = 'd', long)]
+    ib_dev: Option<String>,
+
+    /// port of IB device
+    #[clap(short = 'i', long, default_value = "1")]
+    ib_port: u8,
+
+    /// local port gid index
+    #[clap(short = 'g', long, default_value = "2")]
+    gid_idx: u32,
+
+    /// number of connections
+    #[clap(short = 'c', long, default_value = "4")]
+    conns: u32,
+
+    /// number of messages of each connection
+    #[clap(short = 'n', long, default_value = "16")]
+    msgs: u32,
+
+    /// size of each message
+    #[clap(short = 's', long, default_value = "64")]
+    size: usize,
+}
+
+fn main() -> Result<()> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "rdma_shared_cq=info,rdma=info")
+    }
+
+    tracing_subscriber::fmt::init();
+
+    let args = Args::parse();
+
+    info!("args:\n{:#?}", args);
+
+    run(&args)
+}
+
+/// Encodes the owning connection and the sequence number into a `wr_id`
+fn encode_wr_id(conn: u32, seq: u32) -> u64 {
+    (u64::from(conn) << 32) | u64::from(seq)
+}
+
+/// Decodes a `wr_id` which is encoded by [`encode_wr_id`]
+fn decode_wr_id(wr_id: u64) -> (u32, u32) {
+    (
+        (wr_id >> 32).numeric_cast(),
+        (wr_id & 0xffff_ffff).numeric_cast(),
+    )
+}
+
+/// A loopback RC connection: `sender` sends to `receiver`
+struct Connection {
+    sender: QueuePair,
+    receiver: QueuePair,
+}
+
+/// Which queue pair of a connection a completion belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Side {
+    Sender,
+    Receiver,
+}
+
+fn run(args: &Args) -> Result<()> {
+    ensure!(args.conns > 0 && args.msgs > 0 && args.size > 0);
+
+    let ctx = match args.ib_dev.as_deref() {
+        Some(name) => Context::open_by_name(name)?,
+        None => Context::open_first()?,
+    };
+
+    let pd = ProtectionDomain::alloc(&ctx)?;
+
+    // one slot for each message of each connection
+    let slots: usize = (args.conns * args.msgs).numeric_cast();
+    let buf_size = slots.checked_mul(args.size).unwrap();
+
+    let mut send_buf = vec![0; buf_size];
+    for conn in 0..args.conns {
+        for seq in 0..args.msgs {
+            let offset = slot_index(args, conn, seq) * args.size;
+            let slot = &mut send_buf[offset..offset + args.size];
+            rdma::check::fill_pattern(slot, encode_wr_id(conn, seq));
+        }
+    }
+    let mut recv_buf = vec![0; buf_size];
+
+    let send_mr = unsafe {
+        let access_flags = AccessFlags::LOCAL_WRITE;
+        MemoryRegion::register(&pd, send_buf.as_mut_ptr(), buf_size, access_flags, ())?
+    };
+    let recv_mr = unsafe {
+        let access_flags = AccessFlags::LOCAL_WRITE;
+        MemoryRegion::register(&pd, recv_buf.as_mut_ptr(), buf_size, access_flags, ())?
+    };
+
+    // every send and every receive completes on the shared cq
+    let total = slots * 2;
+    let cq = {
+        let mut options = CompletionQueue::options();
+        options.cqe(total);
+        CompletionQueue::create(&ctx, options)?
+    };
+
+    let create_qp = || {
+        let cap = QueuePairCapacity {
+            max_send_wr: args.msgs,
+            max_recv_wr: args.msgs,
+            max_send_sge: 1,
+            max_recv_sge: 1,
+            max_inline_data: 0,
+        };
+        let mut options = QueuePair::options();
+        options
+            .send_cq(&cq)
+            .recv_cq(&cq)
+            .cap(cap)
+            .qp_type(QueuePairType::RC)
+            .sq_sig_all(true)
+            .pd(&pd);
+        QueuePair::create(&ctx, options)
+    };
+
+    let mut conns = Vec::with_capacity(args.conns.numeric_cast());
+    // the reverse index of `qp_num`
+    let mut owners: HashMap<u32, (u32, Side)> = HashMap::new();
+
+    for conn in 0..args.conns {
+        let sender = create_qp()?;
+        let receiver = create_qp()?;
+
+        rdma::connect::rc_initialize(&receiver, args.ib_port)?;
+        for seq in 0..args.msgs {
+            let sge = recv_mr.chunk(slot_index(args, conn, seq), args.size);
+            let mut recv_wr = RecvRequest::zeroed();
+            recv_wr
+                .id(encode_wr_id(conn, seq))
+                .sg_list(slice::from_ref(&sge));
+            unsafe { receiver.post_recv(&recv_wr)? };
+        }
+
+        rdma::connect::connect_local(&sender, &receiver, args.ib_port, args.gid_idx)?;
+
+        owners.insert(sender.qp_num(), (conn, Side::Sender));
+        owners.insert(receiver.qp_num(), (conn, Side::Receiver));
+        conns.push(Connection { sender, receiver });
+    }
+
+    for (conn, c) in (0..args.conns).zip(&conns) {
+        for seq in 0..args.msgs {
+            let sge = send_mr.chunk(slot_index(args, conn, seq), args.size);
+            let mut send_wr = SendRequest::zeroed();
+            send_wr
+                .id(encode_wr_id(conn, seq))
+                .sg_list(slice::from_ref(&sge))
+                .opcode(wr::Opcode::Send);
+            unsafe { c.sender.post_send(&send_wr)? };
+        }
+    }
+
+    let mut seen: HashMap<(u32, Side), Vec<bool>> = HashMap::new();
+    let mut completed = 0;
+    let mut wcs = CompletionBuffer::new(total.min(64));
+
+    while completed < total {
+        for wc in wcs.poll(&cq)? {
+            let wc = wc.check()?;
+            let (conn, seq) = decode_wr_id(wc.wr_id());
+            ensure!(
+                conn < args.conns && seq < args.msgs,
+                "unexpected wr_id {:#x}",
+                wc.wr_id()
+            );
+
+            let side = match wc.opcode() {
+                wc::Opcode::Send => Side::Sender,
+                wc::Opcode::Recv => Side::Receiver,
+                opcode => return Err(anyhow!("unexpected opcode {:?}", opcode)),
+            };
+
+            // the connection decoded from `wr_id` must own the queue pair of `qp_num`
+            let c = &conns[conn.numeric_cast::<usize>()];
+            let qp = match side {
+                Side::Sender => &c.sender,
+                Side::Receiver => &c.receiver,
+            };
+            ensure!(
+                wc.qp_num() == qp.qp_num() && owners.get(&wc.qp_num()) == Some(&(conn, side)),
+                "misrouted completion: wr_id {:#x} on qp {}",
+                wc.wr_id(),
+                wc.qp_num()
+            );
+
+            let flags = seen
+                .entry((conn, side))
+                .or_insert_with(|| vec![false; args.msgs.numeric_cast()]);
+            let flag = &mut flags[seq.numeric_cast::<usize>()];
+            ensure!(!*flag, "duplicate completion: wr_id {:#x}", wc.wr_id());
+            *flag = true;
+
+            if side == Side::Receiver {
+                let offset = slot_index(args, conn, seq) * args.size;
+                let data = &recv_buf[offset..offset + args.size];
+                if let Err(pos) = rdma::check::verify_pattern(data, wc.wr_id()) {
+                    return Err(anyhow!(
+                        "connection {} message {}: corrupted data at offset {}",
+                        conn,
+                        seq,
+                        pos
+                    ));
+                }
+            }
+
+            debug!(conn, seq, ?side, qp_num = wc.qp_num(), "completion");
+            completed += 1;
+        }
+    }
+
+    println!(
+        "{} connections on one cq: {} sends and {} receives completed and routed correctly",
+        args.conns, slots, slots
+    );
+
+    Ok(())
+}
+
+fn slot_index(args: &Args, conn: u32, seq: u32) -> usize {
+    (conn * args.msgs + seq).numeric_cast()
+}